@@ -0,0 +1,13 @@
+#!/bin/sh
+
+set -e
+
+touch /root/.lnd/start9/dbMigrationConfirmed
+action_result="    {
+    \"version\": \"0\",
+    \"message\": \"LND will use the database backend selected in its config the next time it starts\",
+    \"value\": null,
+    \"copyable\": false,
+    \"qr\": false
+}"
+echo $action_result
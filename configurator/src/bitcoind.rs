@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use bitcoincore_rpc::RpcApi;
+use serde::Deserialize;
+
+use crate::config::{BitcoinCoreConfig, BitcoindAuth, Network};
+use crate::net::tcp_reachable;
+
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection details lnd (and the configurator) use to reach bitcoind.
+#[derive(Debug, Clone)]
+pub struct BitcoindBackend {
+    pub rpc_host: String,
+    pub rpc_port: u16,
+    pub auth: BitcoindAuth,
+    pub zmq_host: String,
+    pub zmq_block_port: u16,
+    pub zmq_tx_port: u16,
+    pub p2p_port: u16,
+}
+
+impl BitcoindBackend {
+    pub fn from_config(config: &BitcoinCoreConfig, network: Network) -> Option<Self> {
+        match config {
+            BitcoinCoreConfig::None => None,
+            BitcoinCoreConfig::Internal { user, password } => Some(BitcoindBackend {
+                rpc_host: "bitcoind.embassy".to_owned(),
+                rpc_port: network.bitcoind_rpc_port(),
+                auth: BitcoindAuth::UserPass {
+                    user: user.clone(),
+                    password: password.clone(),
+                },
+                zmq_host: "bitcoind.embassy".to_owned(),
+                zmq_block_port: 28332,
+                zmq_tx_port: 28333,
+                p2p_port: network.p2p_port(),
+            }),
+            BitcoinCoreConfig::External {
+                host,
+                rpc_port,
+                zmq_host,
+                zmq_block_port,
+                zmq_tx_port,
+                p2p_port,
+                auth,
+            } => Some(BitcoindBackend {
+                rpc_host: host.clone(),
+                rpc_port: rpc_port.unwrap_or_else(|| network.bitcoind_rpc_port()),
+                auth: auth.clone(),
+                zmq_host: zmq_host.clone().unwrap_or_else(|| host.clone()),
+                zmq_block_port: *zmq_block_port,
+                zmq_tx_port: *zmq_tx_port,
+                p2p_port: p2p_port.unwrap_or_else(|| network.p2p_port()),
+            }),
+        }
+    }
+
+    fn rpc_client(&self) -> Result<bitcoincore_rpc::Client, anyhow::Error> {
+        let auth = match &self.auth {
+            BitcoindAuth::UserPass { user, password } => {
+                bitcoincore_rpc::Auth::UserPass(user.clone(), password.clone())
+            }
+            BitcoindAuth::Cookie { path } => bitcoincore_rpc::Auth::CookieFile(path.clone()),
+        };
+        Ok(bitcoincore_rpc::Client::new(
+            &format!("http://{}:{}", self.rpc_host, self.rpc_port),
+            auth,
+        )?)
+    }
+
+    /// Whether bitcoind serves BIP157 compact block filters to its peers, which requires both
+    /// `blockfilterindex=1` and `peerblockfilters=1`.
+    pub fn serves_compact_filters(&self) -> Result<bool, anyhow::Error> {
+        let client = self.rpc_client()?;
+        let index_info: serde_json::Value = client.call("getindexinfo", &[])?;
+        if index_info.get("basic block filter index").is_none() {
+            return Ok(false);
+        }
+        // peerblockfilters is only visible through the service bits it makes bitcoind advertise
+        let network_info: serde_json::Value = client.call("getnetworkinfo", &[])?;
+        Ok(network_info["localservicesnames"]
+            .as_array()
+            .is_some_and(|names| names.iter().any(|name| name == "COMPACT_FILTERS")))
+    }
+
+    /// The address neutrino can reach bitcoind's P2P interface on.
+    pub fn p2p_address(&self) -> String {
+        format!("{}:{}", self.rpc_host, self.p2p_port)
+    }
+
+    /// `estimatesmartfee` for each target, in sat/kvB. Targets bitcoind has no estimate for
+    /// (e.g. during IBD) are left out.
+    pub fn estimate_smart_fees(
+        &self,
+        targets: &[u16],
+    ) -> Result<BTreeMap<u16, u64>, anyhow::Error> {
+        let client = self.rpc_client()?;
+        let mut fees = BTreeMap::new();
+        for &target in targets {
+            if let Some(rate) = client.estimate_smart_fee(target, None)?.fee_rate {
+                fees.insert(target, rate.as_sat());
+            }
+        }
+        Ok(fees)
+    }
+}
+
+pub fn bitcoin_rpc_is_ready(backend: &BitcoindBackend) -> Result<bool, anyhow::Error> {
+    Ok(backend.rpc_client()?.get_best_block_hash().is_ok())
+}
+
+/// The parts of bitcoind's `getblockchaininfo` response we use. Deserialized by hand since the
+/// `bitcoincore-rpc` type cannot parse the `warnings` array newer bitcoind versions return.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockchainInfo {
+    pub blocks: u64,
+    #[serde(rename = "initialblockdownload")]
+    pub initial_block_download: bool,
+    pub pruned: bool,
+    /// Lowest height bitcoind still has the block for, only present when pruned.
+    #[serde(rename = "pruneheight")]
+    pub prune_height: Option<u64>,
+    pub size_on_disk: u64,
+}
+
+pub fn blockchain_info(backend: &BitcoindBackend) -> Result<BlockchainInfo, anyhow::Error> {
+    backend
+        .rpc_client()?
+        .call("getblockchaininfo", &[])
+        .map_err(|e| anyhow::anyhow!("Bitcoin RPC Error {:?}", e))
+}
+
+pub fn bitcoin_is_synced(backend: &BitcoindBackend) -> Result<bool, anyhow::Error> {
+    Ok(!blockchain_info(backend)?.initial_block_download)
+}
+
+/// An entry of bitcoind's `getzmqnotifications` response.
+#[derive(Debug, Deserialize)]
+struct ZmqNotification {
+    #[serde(rename = "type")]
+    kind: String,
+    address: String,
+}
+
+fn resolve(host: &str) -> Vec<IpAddr> {
+    (host, 0)
+        .to_socket_addrs()
+        .map(|addrs| addrs.map(|a| a.ip()).collect())
+        .unwrap_or_default()
+}
+
+/// Whether a published ZMQ `address` (e.g. `tcp://0.0.0.0:28332`) is reachable as `host:port`.
+fn zmq_address_matches(address: &str, host: &str, port: u16) -> bool {
+    let (published_host, published_port) = match address
+        .strip_prefix("tcp://")
+        .and_then(|rest| rest.rsplit_once(':'))
+    {
+        Some(split) => split,
+        None => return false,
+    };
+    if published_port.parse::<u16>().ok() != Some(port) {
+        return false;
+    }
+    let published_host = published_host.trim_start_matches('[').trim_end_matches(']');
+    if matches!(published_host, "0.0.0.0" | "::" | "*") || published_host.eq_ignore_ascii_case(host)
+    {
+        return true;
+    }
+    let published_ips = resolve(published_host);
+    resolve(host).iter().any(|ip| published_ips.contains(ip))
+}
+
+/// Checks that bitcoind publishes the `pubrawblock` and `pubrawtx` ZMQ notifications lnd will
+/// subscribe to. Returns a human readable description of every problem found.
+pub fn check_zmq(backend: &BitcoindBackend) -> Vec<String> {
+    let notifications: Vec<ZmqNotification> = match backend
+        .rpc_client()
+        .and_then(|client| Ok(client.call("getzmqnotifications", &[])?))
+    {
+        Ok(n) => n,
+        Err(e) => return vec![format!("getzmqnotifications failed: {}", e)],
+    };
+    zmq_problems(
+        &notifications,
+        &backend.zmq_host,
+        backend.zmq_block_port,
+        backend.zmq_tx_port,
+    )
+}
+
+/// The problems [`check_zmq`] reports for bitcoind's `notifications`, when lnd subscribes to
+/// blocks on `host:block_port` and to transactions on `host:tx_port`.
+fn zmq_problems(
+    notifications: &[ZmqNotification],
+    host: &str,
+    block_port: u16,
+    tx_port: u16,
+) -> Vec<String> {
+    let expected = [("pubrawblock", block_port), ("pubrawtx", tx_port)];
+    let mut problems = Vec::new();
+    for (kind, port) in expected {
+        let published: Vec<&str> = notifications
+            .iter()
+            .filter(|n| n.kind == kind)
+            .map(|n| n.address.as_str())
+            .collect();
+        if published.is_empty() {
+            problems.push(format!("bitcoind does not publish {}", kind));
+        } else if !published
+            .iter()
+            .any(|address| zmq_address_matches(address, host, port))
+        {
+            problems.push(format!(
+                "bitcoind publishes {} on {} but lnd is configured for tcp://{}:{}",
+                kind,
+                published.join(", "),
+                host,
+                port
+            ));
+        }
+    }
+    problems
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Rpc,
+    RpcCookie,
+    ZmqBlock,
+    ZmqTx,
+}
+
+impl Endpoint {
+    /// Whether lnd can work around this endpoint being unavailable.
+    pub fn is_zmq(&self) -> bool {
+        matches!(self, Endpoint::ZmqBlock | Endpoint::ZmqTx)
+    }
+}
+
+#[derive(Debug)]
+pub struct PreflightFailure {
+    pub endpoint: Endpoint,
+    pub address: String,
+    pub reason: String,
+}
+
+/// Endpoints that failed [`preflight`], each with the reason.
+#[derive(Debug)]
+pub struct PreflightError(pub Vec<PreflightFailure>);
+
+impl PreflightError {
+    pub fn rpc_failed(&self) -> bool {
+        self.0.iter().any(|f| !f.endpoint.is_zmq())
+    }
+}
+
+impl Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bitcoind preflight failed:")?;
+        for failure in &self.0 {
+            let name = match failure.endpoint {
+                Endpoint::Rpc => "RPC",
+                Endpoint::RpcCookie => "RPC cookie",
+                Endpoint::ZmqBlock => "ZMQ rawblock",
+                Endpoint::ZmqTx => "ZMQ rawtx",
+            };
+            write!(f, "\n  - {} {}: {}", name, failure.address, failure.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+/// Checks that every endpoint lnd will use is reachable (and the cookie file readable), so a
+/// misconfigured backend is reported by name instead of as a generic RPC failure.
+pub fn preflight(backend: &BitcoindBackend) -> Result<(), PreflightError> {
+    let mut failures = Vec::new();
+    let endpoints = [
+        (Endpoint::Rpc, &backend.rpc_host, backend.rpc_port),
+        (
+            Endpoint::ZmqBlock,
+            &backend.zmq_host,
+            backend.zmq_block_port,
+        ),
+        (Endpoint::ZmqTx, &backend.zmq_host, backend.zmq_tx_port),
+    ];
+    for (endpoint, host, port) in endpoints {
+        if let Err(reason) = tcp_reachable(host, port, PREFLIGHT_TIMEOUT) {
+            failures.push(PreflightFailure {
+                endpoint,
+                address: format!("{}:{}", host, port),
+                reason,
+            });
+        }
+    }
+    if let BitcoindAuth::Cookie { path } = &backend.auth {
+        if let Err(e) = std::fs::read(path) {
+            failures.push(PreflightFailure {
+                endpoint: Endpoint::RpcCookie,
+                address: path.display().to_string(),
+                reason: e.to_string(),
+            });
+        }
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(PreflightError(failures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zmq_address_matches, zmq_problems, ZmqNotification};
+
+    fn notification(kind: &str, address: &str) -> ZmqNotification {
+        ZmqNotification {
+            kind: kind.to_owned(),
+            address: address.to_owned(),
+        }
+    }
+
+    #[test]
+    fn wildcard_binds_match_any_host() {
+        for address in ["tcp://0.0.0.0:28332", "tcp://[::]:28332", "tcp://*:28332"] {
+            assert!(
+                zmq_address_matches(address, "bitcoind.embassy", 28332),
+                "{}",
+                address
+            );
+            assert!(!zmq_address_matches(address, "bitcoind.embassy", 28333));
+        }
+    }
+
+    #[test]
+    fn matches_bracketed_ipv6() {
+        assert!(zmq_address_matches("tcp://[::1]:28332", "::1", 28332));
+        assert!(zmq_address_matches(
+            "tcp://[2001:db8::1]:28332",
+            "2001:DB8:0::1",
+            28332
+        ));
+        assert!(!zmq_address_matches("tcp://[::1]:28332", "::2", 28332));
+        assert!(!zmq_address_matches("tcp://[::1]:28333", "::1", 28332));
+    }
+
+    #[test]
+    fn matches_hostnames() {
+        assert!(zmq_address_matches(
+            "tcp://Bitcoind.Embassy:28332",
+            "bitcoind.embassy",
+            28332
+        ));
+        // compared by what they resolve to
+        assert!(zmq_address_matches(
+            "tcp://127.0.0.1:28332",
+            "localhost",
+            28332
+        ));
+        assert!(!zmq_address_matches(
+            "tcp://127.0.0.1:28332",
+            "bitcoind.invalid",
+            28332
+        ));
+    }
+
+    #[test]
+    fn rejects_port_mismatches_and_malformed_addresses() {
+        assert!(!zmq_address_matches(
+            "tcp://127.0.0.1:28333",
+            "127.0.0.1",
+            28332
+        ));
+        for address in [
+            "127.0.0.1:28332",
+            "ipc:///tmp/bitcoind.sock",
+            "tcp://127.0.0.1",
+            "tcp://127.0.0.1:port",
+        ] {
+            assert!(
+                !zmq_address_matches(address, "127.0.0.1", 28332),
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn reports_missing_and_mismatched_notifications() {
+        let notifications = [
+            notification("pubrawblock", "tcp://0.0.0.0:28332"),
+            notification("pubhashtx", "tcp://0.0.0.0:28333"),
+        ];
+        assert_eq!(
+            zmq_problems(&notifications, "bitcoind.embassy", 28332, 28333),
+            ["bitcoind does not publish pubrawtx"]
+        );
+
+        let notifications = [
+            notification("pubrawblock", "tcp://0.0.0.0:28332"),
+            notification("pubrawtx", "tcp://127.0.0.1:28333"),
+            notification("pubrawtx", "tcp://0.0.0.0:28334"),
+        ];
+        assert_eq!(
+            zmq_problems(&notifications, "10.0.0.2", 28332, 28333),
+            ["bitcoind publishes pubrawtx on tcp://127.0.0.1:28333, tcp://0.0.0.0:28334 but lnd is configured for tcp://10.0.0.2:28333"]
+        );
+        assert!(zmq_problems(&notifications, "127.0.0.1", 28332, 28333).is_empty());
+    }
+}
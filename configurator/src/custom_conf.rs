@@ -0,0 +1,274 @@
+//! `custom-lnd-conf`: raw `lnd.conf` lines for lnd options `Config` does not model, merged into the
+//! generated file.
+
+use crate::config::Config;
+use crate::lnd_conf::{LndConf, DEFAULT_SECTION};
+use crate::validate::ValidationErrors;
+
+/// Keys that would break the service or put funds at risk, with the reason.
+const DENYLIST: &[(&str, &str)] = &[
+    (
+        "noseedbackup",
+        "it creates a wallet without a seed to recover it from",
+    ),
+    ("no-macaroons", "it disables RPC authentication"),
+    (
+        "notls",
+        "the configurator, health check and dependents only connect to gRPC and REST over TLS",
+    ),
+    (
+        "no-rest-tls",
+        "the configurator, health check and dependents only connect to REST over TLS",
+    ),
+    (
+        "rpclisten",
+        "the gRPC listener is managed by the service and used by its dependents",
+    ),
+    (
+        "restlisten",
+        "the REST listener is managed by the service and used by its dependents",
+    ),
+    ("lnddir", "lnd's data must stay on the service volume"),
+    ("datadir", "lnd's data must stay on the service volume"),
+    (
+        "tlscertpath",
+        "dependents expect the TLS certificate in its default location",
+    ),
+    (
+        "tlskeypath",
+        "the TLS key must stay next to its certificate",
+    ),
+    (
+        "adminmacaroonpath",
+        "dependents expect the macaroons in their default location",
+    ),
+    (
+        "wallet-unlock-password-file",
+        "the wallet is unlocked by the service",
+    ),
+    (
+        "db.backend",
+        "use the db section, which refuses to silently switch away from existing data",
+    ),
+];
+
+/// Splits a `section.key=value` line, rejecting malformed lines and denylisted keys.
+pub fn parse_line(line: &str) -> Result<(&str, &str), String> {
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("expected section.key=value, got {:?}", line))?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        return Err(format!("invalid key {:?}", key));
+    }
+    if let Some((_, reason)) = DENYLIST.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+        return Err(format!("{} cannot be set here: {}", key, reason));
+    }
+    Ok((key, value))
+}
+
+/// Merges `custom-lnd-conf` into the generated `conf`. New keys go to the section named by their
+/// prefix if the file has one, and to `[Application Options]` (where lnd accepts any key)
+/// otherwise. Keys the configurator already wrote, in any case since lnd ignores it, are rejected
+/// unless `custom-lnd-conf-override` is set, in which case they are replaced and a warning naming
+/// each one is returned.
+pub fn merge(conf: &mut LndConf, config: &Config) -> Result<Vec<String>, ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    let mut warnings = Vec::new();
+    let mut custom_keys: Vec<&str> = Vec::new();
+    for (idx, line) in config.custom_lnd_conf.iter().enumerate() {
+        let path = format!("custom-lnd-conf[{}]", idx);
+        let (key, value) = match parse_line(line) {
+            Ok(kv) => kv,
+            Err(e) => {
+                errors.push(path, e);
+                continue;
+            }
+        };
+        if custom_keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            // a repeated key, e.g. a list option
+            if let Some(section) = conf.section_with_key(key) {
+                section.add(key, value);
+            }
+            continue;
+        }
+        match conf.section_with_key(key) {
+            Some(_) if !config.custom_lnd_conf_override => errors.push(
+                path,
+                format!(
+                    "{} is managed by the configurator; use the matching config.yaml setting or enable custom-lnd-conf-override",
+                    key
+                ),
+            ),
+            Some(section) => {
+                warnings.push(format!(
+                    "custom-lnd-conf overrides the managed key {}",
+                    key
+                ));
+                section.set(key, value);
+            }
+            None => {
+                let section = match key.split_once('.') {
+                    Some((prefix, _)) if conf.has_section(prefix) => prefix,
+                    _ => DEFAULT_SECTION,
+                };
+                conf.section(section).add(key, value);
+            }
+        }
+        custom_keys.push(key);
+    }
+    errors.into_result().map(|()| warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upgrade::tests::legacy_config;
+
+    const GENERATED: &str = "\
+[Application Options]
+alias=my node
+maxpendingchannels=5
+
+[routerrpc]
+routerrpc.attemptcost=100
+";
+
+    fn merged(
+        lines: &[&str],
+        override_managed: bool,
+    ) -> (LndConf, Result<Vec<String>, Vec<String>>) {
+        let mut config = legacy_config();
+        config.custom_lnd_conf = lines.iter().map(|line| line.to_string()).collect();
+        config.custom_lnd_conf_override = override_managed;
+        let mut conf = LndConf::parse(GENERATED).unwrap();
+        let result = merge(&mut conf, &config)
+            .map_err(|errors| errors.0.iter().map(ToString::to_string).collect());
+        (conf, result)
+    }
+
+    fn values(conf: &mut LndConf, section: &str, key: &str) -> Vec<String> {
+        conf.section(section)
+            .get_all(key)
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn parses_lines() {
+        assert_eq!(
+            parse_line(" routerrpc.attemptcost = 50 "),
+            Ok(("routerrpc.attemptcost", "50"))
+        );
+        assert_eq!(parse_line("color=#ff9900"), Ok(("color", "#ff9900")));
+        assert!(parse_line("routerrpc.attemptcost").is_err());
+        assert!(parse_line("=50").is_err());
+        assert!(parse_line("bad key=1").is_err());
+    }
+
+    #[test]
+    fn rejects_denied_keys() {
+        let e = parse_line("noseedbackup=true").unwrap_err();
+        assert!(e.contains("without a seed"), "{}", e);
+        assert!(parse_line("RPCListen=0.0.0.0:10010").is_err());
+        assert!(parse_line("db.backend=sqlite").is_err());
+        assert!(parse_line("notls=true").is_err());
+        assert!(parse_line("No-REST-TLS=1").is_err());
+
+        let (_, result) = merged(&["coin-selection-strategy=random", "no-macaroons=1"], true);
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].starts_with("custom-lnd-conf[1]: "),
+            "{}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn rejects_managed_keys_without_override() {
+        let (mut conf, result) = merged(
+            &["maxpendingchannels=10", "routerrpc.attemptcost=50"],
+            false,
+        );
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("custom-lnd-conf[0]: maxpendingchannels is managed"));
+        assert!(errors[1].starts_with("custom-lnd-conf[1]: routerrpc.attemptcost is managed"));
+        assert_eq!(
+            values(&mut conf, DEFAULT_SECTION, "maxpendingchannels"),
+            ["5"]
+        );
+
+        // lnd reads keys case-insensitively, so ALIAS would set the managed alias as well
+        let (_, result) = merged(&["ALIAS=x"], false);
+        assert_eq!(
+            result.unwrap_err(),
+            ["custom-lnd-conf[0]: ALIAS is managed by the configurator; use the matching config.yaml setting or enable custom-lnd-conf-override"]
+        );
+    }
+
+    #[test]
+    fn override_replaces_managed_keys_with_a_warning() {
+        let (mut conf, result) = merged(&["routerrpc.attemptcost=50"], true);
+        assert_eq!(
+            result.unwrap(),
+            ["custom-lnd-conf overrides the managed key routerrpc.attemptcost"]
+        );
+        assert_eq!(
+            values(&mut conf, "routerrpc", "routerrpc.attemptcost"),
+            ["50"]
+        );
+
+        let (conf, result) = merged(&["Alias=x"], true);
+        assert_eq!(result.unwrap().len(), 1);
+        assert_eq!(conf.to_string().matches("lias=").count(), 1);
+        assert!(conf.to_string().contains("alias=x\n"));
+    }
+
+    #[test]
+    fn appends_new_keys_to_their_section() {
+        let (mut conf, result) = merged(
+            &[
+                "routerrpc.maxmchistory=2000",
+                "coin-selection-strategy=random",
+                "healthcheck.disk.interval=12h",
+            ],
+            false,
+        );
+        assert_eq!(result.unwrap(), Vec::<String>::new());
+        assert_eq!(
+            values(&mut conf, "routerrpc", "routerrpc.maxmchistory"),
+            ["2000"]
+        );
+        assert_eq!(
+            values(&mut conf, DEFAULT_SECTION, "coin-selection-strategy"),
+            ["random"]
+        );
+        // no [healthcheck] section was generated, so lnd reads it from Application Options
+        assert!(!conf.has_section("healthcheck"));
+        assert_eq!(
+            values(&mut conf, DEFAULT_SECTION, "healthcheck.disk.interval"),
+            ["12h"]
+        );
+    }
+
+    #[test]
+    fn repeats_keys_given_more_than_once() {
+        let (mut conf, result) = merged(&["tlsextraip=10.0.0.1", "tlsextraip=10.0.0.2"], false);
+        assert!(result.is_ok());
+        assert_eq!(
+            values(&mut conf, DEFAULT_SECTION, "tlsextraip"),
+            ["10.0.0.1", "10.0.0.2"]
+        );
+
+        // an overridden list option replaces the generated values once, then appends
+        let (mut conf, result) = merged(&["alias=a", "ALIAS=b"], true);
+        assert_eq!(result.unwrap().len(), 1);
+        assert_eq!(values(&mut conf, DEFAULT_SECTION, "alias"), ["a", "b"]);
+    }
+}
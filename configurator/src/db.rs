@@ -0,0 +1,181 @@
+//! lnd does not migrate its data when `db.backend` changes, it just starts over with an empty
+//! database. We remember which backend holds a node's data and refuse to switch away from it
+//! until the user confirms a migration with the `confirm-db-migration` action.
+
+use std::path::Path;
+
+use crate::config::{DbConfig, Network};
+
+const LND_DIR: &str = "/root/.lnd";
+
+/// Backend that holds this node's data, relative to `/root/.lnd`. Written by [`record_backend`]
+/// once lnd's server is up on it, or by [`check_backend`] after a confirmed migration.
+const MARKER: &str = "start9/dbBackend";
+/// Created by the `confirm-db-migration` action once the user migrated the data to the selected
+/// backend.
+const CONFIRM_MIGRATION: &str = "start9/dbMigrationConfirmed";
+
+/// lnd's channel database file for a file based `backend`, relative to `/root/.lnd`.
+fn database_file(backend: &str, network: Network) -> Option<String> {
+    let file = match backend {
+        "bolt" => "channel.db",
+        "sqlite" => "channel.sqlite",
+        _ => return None,
+    };
+    Some(format!("data/graph/{}/{}", network, file))
+}
+
+/// Backend holding the data in `lnd_dir`, if there is any. Nodes set up before the backend was
+/// recorded are recognized by their database files.
+fn existing_backend(lnd_dir: &Path, network: Network) -> Result<Option<String>, anyhow::Error> {
+    match std::fs::read_to_string(lnd_dir.join(MARKER)) {
+        Ok(backend) => return Ok(Some(backend.trim().to_owned())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(["bolt", "sqlite"]
+        .iter()
+        .find(|backend| {
+            database_file(backend, network).is_some_and(|file| lnd_dir.join(file).exists())
+        })
+        .map(|backend| (*backend).to_owned()))
+}
+
+/// Fails with migration instructions if the data in `/root/.lnd` lives in a different backend than
+/// the one selected. After a confirmed migration the selected backend is recorded instead.
+pub fn check_backend(db: &DbConfig, network: Network) -> Result<(), anyhow::Error> {
+    check(Path::new(LND_DIR), db, network)
+}
+
+fn check(lnd_dir: &Path, db: &DbConfig, network: Network) -> Result<(), anyhow::Error> {
+    let selected = db.backend_name();
+    let confirm = lnd_dir.join(CONFIRM_MIGRATION);
+    if confirm.exists() {
+        if let Some(file) = database_file(selected, network) {
+            if !lnd_dir.join(&file).exists() {
+                anyhow::bail!(
+                    "a database migration was confirmed, but there is no {selected} database at \
+                     {}: migrate the data with `lndinit migrate-db` before starting LND on \
+                     {selected}, or select db.backend {} again.",
+                    lnd_dir.join(file).display(),
+                    existing_backend(lnd_dir, network)?.unwrap_or_else(|| "bolt".to_owned())
+                );
+            }
+        }
+        println!(
+            "recording {} as lnd's database backend after a confirmed migration",
+            selected
+        );
+        std::fs::write(lnd_dir.join(MARKER), selected)?;
+        std::fs::remove_file(confirm)?;
+        return Ok(());
+    }
+    match existing_backend(lnd_dir, network)? {
+        Some(existing) if existing != selected => anyhow::bail!(
+            "refusing to switch lnd's database from {existing} to {selected}: lnd would start over \
+             with an empty {selected} database and lose track of the existing wallet and channels. \
+             To move the data, stop LND, migrate the data with `lndinit migrate-db` \
+             (https://github.com/lightninglabs/lndinit), then run the Confirm Database Migration \
+             action and start LND again. Otherwise select db.backend {existing} again."
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Records the selected backend as the one holding this node's data. Only called once lnd's
+/// server is active, so a first start that fails before lnd created its database does not tie the
+/// node to a backend it never used.
+pub fn record_backend(db: &DbConfig) -> Result<(), anyhow::Error> {
+    record(Path::new(LND_DIR), db)
+}
+
+fn record(lnd_dir: &Path, db: &DbConfig) -> Result<(), anyhow::Error> {
+    let marker = lnd_dir.join(MARKER);
+    if marker.exists() {
+        return Ok(());
+    }
+    println!("recording {} as lnd's database backend", db.backend_name());
+    std::fs::write(marker, db.backend_name())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{check, record, CONFIRM_MIGRATION, MARKER};
+    use crate::config::{DbConfig, Network};
+
+    fn lnd_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("start9")).unwrap();
+        std::fs::create_dir_all(dir.path().join("data/graph/mainnet")).unwrap();
+        dir
+    }
+
+    fn touch(lnd_dir: &Path, file: &str) {
+        std::fs::write(lnd_dir.join(file), "").unwrap();
+    }
+
+    fn postgres() -> DbConfig {
+        DbConfig::Postgres {
+            dsn: "postgres://lnd@postgres.embassy/lnd".to_owned(),
+            max_connections: None,
+        }
+    }
+
+    #[test]
+    fn new_nodes_take_any_backend() {
+        let dir = lnd_dir();
+        for db in [DbConfig::Bolt, DbConfig::Sqlite, postgres()].iter() {
+            check(dir.path(), db, Network::Mainnet).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_the_recorded_or_detected_backend() {
+        let dir = lnd_dir();
+        touch(dir.path(), "data/graph/mainnet/channel.db");
+        check(dir.path(), &DbConfig::Bolt, Network::Mainnet).unwrap();
+        let err = check(dir.path(), &DbConfig::Sqlite, Network::Mainnet).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("refusing to switch lnd's database from bolt to sqlite:"));
+        // another network's database files say nothing about this one
+        check(dir.path(), &DbConfig::Sqlite, Network::Testnet).unwrap();
+
+        // the marker wins over the files and is only written once
+        record(dir.path(), &postgres()).unwrap();
+        record(dir.path(), &DbConfig::Bolt).unwrap();
+        check(dir.path(), &postgres(), Network::Mainnet).unwrap();
+        check(dir.path(), &DbConfig::Bolt, Network::Mainnet).unwrap_err();
+    }
+
+    #[test]
+    fn confirmed_migration_records_the_selected_backend() {
+        let dir = lnd_dir();
+        touch(dir.path(), "data/graph/mainnet/channel.db");
+        touch(dir.path(), CONFIRM_MIGRATION);
+
+        // the sqlite database has not been created by lndinit yet
+        let err = check(dir.path(), &DbConfig::Sqlite, Network::Mainnet).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("a database migration was confirmed, but there is no sqlite database"));
+        assert!(dir.path().join(CONFIRM_MIGRATION).exists());
+
+        touch(dir.path(), "data/graph/mainnet/channel.sqlite");
+        check(dir.path(), &DbConfig::Sqlite, Network::Mainnet).unwrap();
+        assert!(!dir.path().join(CONFIRM_MIGRATION).exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(MARKER)).unwrap(),
+            "sqlite"
+        );
+        check(dir.path(), &DbConfig::Bolt, Network::Mainnet).unwrap_err();
+
+        // postgres data cannot be checked from here
+        touch(dir.path(), CONFIRM_MIGRATION);
+        check(dir.path(), &postgres(), Network::Mainnet).unwrap();
+        check(dir.path(), &postgres(), Network::Mainnet).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+//! Serves bitcoind's `estimatesmartfee` results in the format lnd expects from `fee.url`, so a
+//! neutrino backed lnd can take its fee estimates from our own bitcoind instead of a third party.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+use crate::bitcoind::BitcoindBackend;
+
+pub const LOCAL_FEE_PORT: u16 = 8099;
+const CONF_TARGETS: &[u16] = &[2, 3, 6, 12, 24, 144, 504, 1008];
+
+pub fn local_fee_url() -> String {
+    format!("http://{}:{}/fees", Ipv4Addr::LOCALHOST, LOCAL_FEE_PORT)
+}
+
+/// Whether bitcoind is reachable and has enough data to estimate fees right now.
+pub fn bitcoind_can_estimate(backend: &BitcoindBackend) -> bool {
+    matches!(backend.estimate_smart_fees(CONF_TARGETS), Ok(fees) if !fees.is_empty())
+}
+
+/// The `fee.url` document for `fees`, in sat/kvB by confirmation target.
+fn fee_document(fees: &BTreeMap<u16, u64>) -> Result<String, anyhow::Error> {
+    if fees.is_empty() {
+        anyhow::bail!("bitcoind cannot estimate fees yet");
+    }
+    Ok(serde_json::json!({ "fee_by_block_target": fees }).to_string())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// The document to serve for a fresh estimate, which falls back to the `last_good` one while
+/// bitcoind fails to estimate, so lnd keeps its last known fees. `None` before any estimate.
+fn current_document(
+    estimate: Result<String, anyhow::Error>,
+    last_good: &mut Option<String>,
+) -> Option<String> {
+    match estimate {
+        Ok(body) => {
+            *last_good = Some(body.clone());
+            Some(body)
+        }
+        Err(e) => {
+            eprintln!("fee estimation failed: {}", e);
+            last_good.clone()
+        }
+    }
+}
+
+fn handle(stream: &mut TcpStream, backend: &BitcoindBackend, last_good: &mut Option<String>) {
+    // we serve the same document for every request, so only drain the request head
+    let mut buf = [0; 1024];
+    let _ = stream.read(&mut buf);
+    let estimate = backend
+        .estimate_smart_fees(CONF_TARGETS)
+        .and_then(|fees| fee_document(&fees));
+    let res = match current_document(estimate, last_good) {
+        Some(body) => respond(stream, "200 OK", &body),
+        None => respond(stream, "503 Service Unavailable", "{}"),
+    };
+    if let Err(e) = res {
+        eprintln!("failed to answer fee request: {}", e);
+    }
+}
+
+/// Starts answering fee requests on [`local_fee_url`] in a background thread. Must be called
+/// after daemonizing, since threads do not survive a fork.
+pub fn spawn_server(backend: BitcoindBackend) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, LOCAL_FEE_PORT))?;
+    std::thread::spawn(move || {
+        let mut last_good = None;
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => handle(&mut stream, &backend, &mut last_good),
+                Err(e) => eprintln!("fee server connection failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{current_document, fee_document};
+
+    #[test]
+    fn documents_fees_by_block_target() {
+        let fees = [(2, 25_000), (144, 1_000)].iter().copied().collect();
+        assert_eq!(
+            fee_document(&fees).unwrap(),
+            r#"{"fee_by_block_target":{"144":1000,"2":25000}}"#
+        );
+        // bitcoind in IBD has no estimates for any target
+        assert!(fee_document(&Default::default()).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_the_last_good_document() {
+        let mut last_good = None;
+        let failed = || Err(anyhow::anyhow!("bitcoind cannot estimate fees yet"));
+        assert_eq!(current_document(failed(), &mut last_good), None);
+
+        let first = "{\"fee_by_block_target\":{\"2\":25000}}".to_owned();
+        assert_eq!(
+            current_document(Ok(first.clone()), &mut last_good),
+            Some(first.clone())
+        );
+        assert_eq!(current_document(failed(), &mut last_good), Some(first));
+
+        let second = "{\"fee_by_block_target\":{\"2\":30000}}".to_owned();
+        current_document(Ok(second.clone()), &mut last_good);
+        assert_eq!(current_document(failed(), &mut last_good), Some(second));
+    }
+}
@@ -0,0 +1,544 @@
+use std::net::{IpAddr, SocketAddr};
+
+use crate::bitcoind::BitcoindBackend;
+use crate::config::{
+    BitcoindAuth, Config, DbConfig, Estimator, FeeSource, Network, OnionConfig, TorControlAuth,
+    WtClient,
+};
+use crate::fees;
+use crate::lnd_conf::{LndConf, DEFAULT_SECTION};
+use crate::net::HostPort;
+pub fn backup_ignore(network: Network) -> String {
+    format!(include_str!(".backupignore.template"), network = network)
+}
+
+/// Everything besides `config.yaml` that influences the generated `lnd.conf`.
+pub struct RenderContext<'a> {
+    pub alias: &'a str,
+    /// Addresses the gRPC and REST listeners bind to, see [`crate::net::container_ips`].
+    pub listen_ips: Vec<IpAddr>,
+    pub use_neutrino: bool,
+    /// Have lnd poll bitcoind over RPC instead of subscribing to its ZMQ notifications.
+    pub rpc_polling: bool,
+    /// bitcoind can currently answer `estimatesmartfee`, see [`FeeSource::Bitcoind`].
+    pub bitcoind_fees: bool,
+    /// bitcoind serves compact block filters, so neutrino adds it next to the strangers on the
+    /// internet, unless the user picked their own `neutrino.connect` peers. See
+    /// [`crate::neutrino::bitcoind_serves_neutrino`].
+    pub bitcoind_neutrino_peer: bool,
+    pub bitcoind: Option<&'a BitcoindBackend>,
+}
+
+pub fn lnd_conf(config: &Config, ctx: &RenderContext) -> LndConf {
+    let advanced = &config.advanced;
+    let mut conf = LndConf::new();
+
+    let app = conf.section(DEFAULT_SECTION);
+    if let OnionConfig::Start9 = config.tor.onion {
+        // lnd refuses externalhosts together with nat, but takes onions as externalip too
+        let key = if config.nat {
+            "externalip"
+        } else {
+            "externalhosts"
+        };
+        app.add(key, &config.peer_tor_address);
+    }
+    for address in config
+        .externalip
+        .iter()
+        .filter_map(|address| HostPort::parse(address).ok())
+    {
+        // DNS names go to externalhosts, which lnd re-resolves to follow a dynamic IP
+        let key = if address.host.parse::<IpAddr>().is_ok() {
+            "externalip"
+        } else {
+            "externalhosts"
+        };
+        let port = address.port_or(config.ports.peer);
+        app.add(
+            key,
+            HostPort {
+                port: Some(port),
+                ..address
+            },
+        );
+    }
+    if config.nat {
+        app.set("nat", true);
+    }
+    app.set(
+        "payments-expiration-grace-period",
+        format!("{}s", advanced.payments_expiration_grace_period),
+    )
+    .set("listen", format!("0.0.0.0:{}", config.ports.peer));
+    for ip in &ctx.listen_ips {
+        app.add("rpclisten", SocketAddr::new(*ip, config.ports.rpc));
+    }
+    for ip in &ctx.listen_ips {
+        app.add("restlisten", SocketAddr::new(*ip, config.ports.rest));
+    }
+    app.set("rpcmiddleware.enable", true)
+        .set("debuglevel", advanced.logging.debug_level())
+        .set_opt("maxlogfiles", advanced.logging.max_log_files)
+        .set_opt("maxlogfilesize", advanced.logging.max_log_file_size)
+        .set_opt("minchansize", config.min_chan_size)
+        .set_opt("maxchansize", config.max_chan_size)
+        .set(
+            "default-remote-max-htlcs",
+            advanced.default_remote_max_htlcs,
+        )
+        .set("rejecthtlc", config.reject_htlc)
+        .set(
+            "max-channel-fee-allocation",
+            advanced.max_channel_fee_allocation,
+        )
+        .set("maxpendingchannels", advanced.max_pending_channels)
+        .set(
+            "max-commit-fee-rate-anchors",
+            advanced.max_commit_fee_rate_anchors,
+        )
+        .set("accept-keysend", config.accept_keysend)
+        .set("accept-amp", config.accept_amp)
+        .set(
+            "gc-canceled-invoices-on-startup",
+            advanced.gc_canceled_invoices_on_startup,
+        )
+        .set("allow-circular-route", advanced.allow_circular_route)
+        .set("numgraphsyncpeers", config.gossip.sync_peers)
+        .set(
+            "ignore-historical-gossip-filters",
+            config.gossip.ignore_historical_filters,
+        )
+        .set("alias", ctx.alias)
+        .set("color", format!("#{}", config.color));
+    if ctx.use_neutrino {
+        let fee_url = match &config.neutrino.fee_source {
+            FeeSource::Url { url } => Some(url.clone()),
+            FeeSource::Bitcoind if ctx.bitcoind_fees => Some(fees::local_fee_url()),
+            FeeSource::Default | FeeSource::Bitcoind => config.network.fee_url().map(str::to_owned),
+        };
+        conf.section(DEFAULT_SECTION).set_opt("fee.url", fee_url);
+    }
+
+    conf.section("Bitcoin")
+        .set(&format!("bitcoin.{}", config.network), true)
+        .set(
+            "bitcoin.node",
+            if ctx.use_neutrino {
+                "neutrino"
+            } else {
+                "bitcoind"
+            },
+        )
+        .set(
+            "bitcoin.defaultchanconfs",
+            advanced.bitcoin.default_channel_confirmations,
+        )
+        .set("bitcoin.minhtlc", advanced.bitcoin.min_htlc)
+        .set("bitcoin.minhtlcout", advanced.bitcoin.min_htlc_out)
+        .set("bitcoin.basefee", advanced.bitcoin.base_fee)
+        .set("bitcoin.feerate", advanced.bitcoin.fee_rate)
+        .set("bitcoin.timelockdelta", advanced.bitcoin.time_lock_delta);
+
+    if let Some(bitcoind) = ctx.bitcoind {
+        let section = conf.section("Bitcoind");
+        section.set(
+            "bitcoind.rpchost",
+            format!("{}:{}", bitcoind.rpc_host, bitcoind.rpc_port),
+        );
+        match &bitcoind.auth {
+            BitcoindAuth::UserPass { user, password } => {
+                section
+                    .set("bitcoind.rpcuser", user)
+                    .set("bitcoind.rpcpass", password);
+            }
+            BitcoindAuth::Cookie { path } => {
+                section.set("bitcoind.rpccookie", path.display());
+            }
+        }
+        if ctx.rpc_polling {
+            section.set("bitcoind.rpcpolling", true);
+        } else {
+            section
+                .set(
+                    "bitcoind.zmqpubrawblock",
+                    format!("tcp://{}:{}", bitcoind.zmq_host, bitcoind.zmq_block_port),
+                )
+                .set(
+                    "bitcoind.zmqpubrawtx",
+                    format!("tcp://{}:{}", bitcoind.zmq_host, bitcoind.zmq_tx_port),
+                );
+        }
+    }
+
+    let neutrino = &config.neutrino;
+    let bitcoind_peer = ctx
+        .bitcoind
+        .filter(|_| ctx.bitcoind_neutrino_peer && neutrino.connect.is_empty())
+        .map(|bitcoind| bitcoind.p2p_address());
+    if ctx.use_neutrino
+        && !(neutrino.connect.is_empty()
+            && neutrino.add_peers.is_empty()
+            && bitcoind_peer.is_none())
+    {
+        let section = conf.section("neutrino");
+        for peer in &neutrino.connect {
+            section.add("neutrino.connect", peer);
+        }
+        for peer in neutrino.add_peers.iter().chain(&bitcoind_peer) {
+            section.add("neutrino.addpeer", peer);
+        }
+    }
+
+    let autopilot = &config.autopilot;
+    conf.section("autopilot")
+        .set("autopilot.active", autopilot.enabled)
+        .set("autopilot.maxchannels", autopilot.maxchannels)
+        .set("autopilot.allocation", autopilot.allocation / 100.0)
+        .set("autopilot.minchansize", autopilot.min_channel_size)
+        .set("autopilot.maxchansize", autopilot.max_channel_size)
+        .set("autopilot.private", autopilot.private)
+        .set("autopilot.minconfs", autopilot.advanced.min_confirmations)
+        .set(
+            "autopilot.conftarget",
+            autopilot.advanced.confirmation_target,
+        );
+
+    let tor = conf.section("tor");
+    tor.set("tor.active", true)
+        .set("tor.socks", &config.tor.socks)
+        .set(
+            "tor.skip-proxy-for-clearnet-targets",
+            !config.tor.use_tor_only,
+        )
+        .set("tor.streamisolation", config.tor.stream_isolation);
+    if let Some(control) = &config.tor.control {
+        tor.set("tor.control", &control.address);
+        // without a password lnd authenticates with the cookie file Tor reports
+        if let TorControlAuth::Password { password } = &control.auth {
+            tor.set("tor.password", password);
+        }
+    }
+    if let OnionConfig::AutoCreate {
+        private_key_path,
+        encrypt_key,
+        target_ip_address,
+    } = &config.tor.onion
+    {
+        tor.set("tor.v3", true)
+            .set_opt(
+                "tor.privatekeypath",
+                private_key_path.as_ref().map(|p| p.display()),
+            )
+            .set("tor.encryptkey", encrypt_key)
+            .set_opt("tor.targetipaddress", target_ip_address.as_ref());
+    }
+
+    conf.section("watchtower")
+        .set("watchtower.active", config.watchtowers.wt_server)
+        .set(
+            "watchtower.listen",
+            format!("0.0.0.0:{}", config.ports.watchtower),
+        )
+        .set(
+            "watchtower.externalip",
+            format!(
+                "{}:{}",
+                config.watchtower_tor_address, config.ports.watchtower
+            ),
+        );
+
+    conf.section("wtclient").set(
+        "wtclient.active",
+        !matches!(config.watchtowers.wt_client, WtClient::Disabled),
+    );
+
+    conf.section("healthcheck")
+        .set("healthcheck.chainbackend.attempts", 5);
+
+    conf.section("protocol")
+        .set("protocol.wumbo-channels", advanced.protocol_wumbo_channels)
+        .set("protocol.no-anchors", advanced.protocol_no_anchors)
+        .set(
+            "protocol.no-script-enforced-lease",
+            advanced.protocol_disable_script_enforced_lease,
+        )
+        .set(
+            "protocol.option-scid-alias",
+            advanced.protocol_option_scid_alias,
+        )
+        .set("protocol.zero-conf", advanced.protocol_zero_conf)
+        .set(
+            "protocol.simple-taproot-chans",
+            advanced.protocol_simple_taproot_chans,
+        );
+
+    let routing = &config.routing;
+    let routerrpc = conf.section("routerrpc");
+    routerrpc
+        .set("routerrpc.minrtprob", routing.min_route_probability)
+        .set("routerrpc.attemptcost", routing.attempt_cost)
+        .set("routerrpc.attemptcostppm", routing.attempt_cost_ppm)
+        .set("routerrpc.maxmchistory", routing.max_mc_history);
+    match &routing.estimator {
+        Estimator::Apriori(apriori) => routerrpc
+            .set("routerrpc.estimator", "apriori")
+            .set("routerrpc.apriori.hopprob", apriori.hop_probability)
+            .set("routerrpc.apriori.weight", apriori.weight)
+            .set(
+                "routerrpc.apriori.penaltyhalflife",
+                format!("{}s", apriori.penalty_half_life),
+            )
+            .set(
+                "routerrpc.apriori.capacityfraction",
+                apriori.capacity_fraction,
+            ),
+        Estimator::Bimodal(bimodal) => routerrpc
+            .set("routerrpc.estimator", "bimodal")
+            .set("routerrpc.bimodal.scale", bimodal.scale)
+            .set("routerrpc.bimodal.nodeweight", bimodal.node_weight)
+            .set(
+                "routerrpc.bimodal.decaytime",
+                format!("{}h", bimodal.decay_time),
+            ),
+    };
+
+    let gossip = &config.gossip;
+    conf.section("gossip")
+        .set(
+            "gossip.max-channel-update-burst",
+            gossip.max_channel_update_burst,
+        )
+        .set(
+            "gossip.channel-update-interval",
+            format!("{}s", gossip.channel_update_interval),
+        )
+        .set("gossip.msg-rate-bytes", gossip.msg_rate_bytes)
+        .set("gossip.msg-burst-bytes", gossip.msg_burst_bytes);
+    conf.section("routing")
+        .set("routing.strictgraphpruning", gossip.strict_graph_pruning);
+    conf.section("caches")
+        .set("caches.channel-cache-size", gossip.channel_cache_size)
+        .set("caches.reject-cache-size", gossip.reject_cache_size);
+
+    let sweeper = &advanced.sweeper;
+    conf.section("sweeper")
+        .set("sweeper.maxfeerate", sweeper.sweeper_maxfeerate)
+        .set(
+            "sweeper.nodeadlineconftarget",
+            sweeper.sweeper_nodeadlineconftarget,
+        )
+        .set(
+            "sweeper.budget.tolocalratio",
+            sweeper.sweeper_budget_tolocalratio,
+        )
+        .set(
+            "sweeper.budget.anchorcpfpratio",
+            sweeper.sweeper_budget_anchorcpfpratio,
+        )
+        .set(
+            "sweeper.budget.deadlinehtlcratio",
+            sweeper.sweeper_budget_deadlinehtlcratio,
+        )
+        .set(
+            "sweeper.budget.nodeadlinehtlcratio",
+            sweeper.sweeper_budget_nodeadlinehtlcratio,
+        );
+
+    let db = conf.section("db");
+    db.set("db.backend", config.db.backend_name())
+        .set("db.no-graph-cache", config.gossip.no_graph_cache);
+    if let DbConfig::Postgres {
+        dsn,
+        max_connections,
+    } = &config.db
+    {
+        db.set("db.postgres.dsn", dsn)
+            .set_opt("db.postgres.maxconnections", *max_connections);
+    }
+
+    if config.db != DbConfig::Bolt {
+        return conf;
+    }
+    conf.section("bolt")
+        .set("db.bolt.nofreelistsync", advanced.db_bolt_no_freelist_sync)
+        .set("db.bolt.auto-compact", advanced.db_bolt_auto_compact)
+        .set(
+            "db.bolt.auto-compact-min-age",
+            format!("{}h", advanced.db_bolt_auto_compact_min_age),
+        )
+        .set(
+            "db.bolt.dbtimeout",
+            format!("{}s", advanced.db_bolt_db_timeout),
+        );
+
+    conf
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::path::Path;
+
+    use super::{lnd_conf, RenderContext};
+    use crate::bitcoind::BitcoindBackend;
+    use crate::config::{BitcoinCoreConfig, Config, FeeSource, Network};
+    use crate::fees;
+    use crate::lnd_conf::{LndConf, DEFAULT_SECTION};
+    use crate::upgrade::tests::legacy_config;
+
+    /// lnd.conf as a real start on the container address `172.18.0.2` generates it. lnd runs on
+    /// neutrino without bitcoind, or with `bitcoind_in_ibd`.
+    fn render(
+        config: &Config,
+        bitcoind_in_ibd: bool,
+        rpc_polling: bool,
+        bitcoind_neutrino_peer: bool,
+    ) -> LndConf {
+        let bitcoind = BitcoindBackend::from_config(&config.bitcoind, config.network);
+        lnd_conf(
+            config,
+            &RenderContext {
+                alias: "golden-alias",
+                listen_ips: vec![Ipv4Addr::new(172, 18, 0, 2).into()],
+                use_neutrino: bitcoind.is_none() || bitcoind_in_ibd,
+                rpc_polling,
+                bitcoind_fees: false,
+                bitcoind_neutrino_peer,
+                bitcoind: bitcoind.as_ref(),
+            },
+        )
+    }
+
+    /// Checks the lnd.conf for every network and backend against `src/golden/`. The backends are
+    /// those of `configurator render --backend`, plus `none` for a config without bitcoind. Run
+    /// with `UPDATE_GOLDEN=1` to rewrite the files after an intended change, then review their
+    /// diff.
+    #[test]
+    fn matches_golden_files() {
+        let networks = [
+            Network::Mainnet,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ];
+        let backends = ["bitcoind", "bitcoind-polling", "neutrino", "none"];
+        let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/golden");
+        let mut mismatches = Vec::new();
+        for network in networks.iter().copied() {
+            for backend in backends.iter().copied() {
+                let mut config = legacy_config();
+                config.network = network;
+                if backend == "none" {
+                    config.bitcoind = BitcoinCoreConfig::None;
+                }
+                let rendered = render(
+                    &config,
+                    backend == "neutrino",
+                    backend == "bitcoind-polling",
+                    false,
+                )
+                .to_string();
+
+                let path = golden_dir.join(format!("{}-{}.conf", network, backend));
+                if std::env::var_os("UPDATE_GOLDEN").is_some() {
+                    std::fs::write(&path, &rendered).unwrap();
+                } else if std::fs::read_to_string(&path).ok().as_ref() != Some(&rendered) {
+                    mismatches.push(path.display().to_string());
+                }
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "lnd.conf differs from {:?}, rerun with UPDATE_GOLDEN=1 if that is intended",
+            mismatches
+        );
+    }
+
+    #[test]
+    fn fee_url_follows_the_fee_source() {
+        let fee_url = |config: &Config, bitcoind_fees: bool| {
+            let mut conf = lnd_conf(
+                config,
+                &RenderContext {
+                    alias: "golden-alias",
+                    listen_ips: vec![Ipv4Addr::new(172, 18, 0, 2).into()],
+                    use_neutrino: true,
+                    rpc_polling: false,
+                    bitcoind_fees,
+                    bitcoind_neutrino_peer: false,
+                    bitcoind: None,
+                },
+            );
+            conf.section(DEFAULT_SECTION)
+                .get_all("fee.url")
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        let mut config = legacy_config();
+        let public = Network::Mainnet.fee_url().unwrap();
+        assert_eq!(fee_url(&config, true), [public]);
+
+        config.neutrino.fee_source = FeeSource::Bitcoind;
+        assert_eq!(fee_url(&config, true), [fees::local_fee_url()]);
+        // bitcoind has no estimates yet
+        assert_eq!(fee_url(&config, false), [public]);
+
+        config.neutrino.fee_source = FeeSource::Url {
+            url: "https://fees.example.com/v1/btc-fee-estimates.json".to_owned(),
+        };
+        assert_eq!(
+            fee_url(&config, false),
+            ["https://fees.example.com/v1/btc-fee-estimates.json"]
+        );
+    }
+
+    fn neutrino_peers(conf: &mut LndConf) -> (Vec<String>, Vec<String>) {
+        let section = conf.section("neutrino");
+        let peers = |key| section.get_all(key).map(str::to_owned).collect::<Vec<_>>();
+        (peers("neutrino.connect"), peers("neutrino.addpeer"))
+    }
+
+    #[test]
+    fn adds_bitcoind_next_to_public_neutrino_peers() {
+        let mut config = legacy_config();
+        assert!(!render(&config, true, false, false).has_section("neutrino"));
+
+        let mut conf = render(&config, true, false, true);
+        assert_eq!(
+            neutrino_peers(&mut conf),
+            (vec![], vec!["bitcoind.embassy:8333".to_owned()])
+        );
+
+        config.neutrino.add_peers = vec!["peer.example.com".to_owned()];
+        let mut conf = render(&config, true, false, true);
+        assert_eq!(
+            neutrino_peers(&mut conf),
+            (
+                vec![],
+                vec![
+                    "peer.example.com".to_owned(),
+                    "bitcoind.embassy:8333".to_owned()
+                ]
+            )
+        );
+
+        // bitcoind out of IBD is lnd's backend, so neutrino is not configured at all
+        assert!(!render(&config, false, false, true).has_section("neutrino"));
+    }
+
+    #[test]
+    fn skips_bitcoind_when_neutrino_peers_are_pinned() {
+        let mut config = legacy_config();
+        config.neutrino.connect = vec!["node.example.com:8333".to_owned()];
+        config.neutrino.add_peers = vec!["peer.example.com".to_owned()];
+        let mut conf = render(&config, true, false, true);
+        assert_eq!(
+            neutrino_peers(&mut conf),
+            (
+                vec!["node.example.com:8333".to_owned()],
+                vec!["peer.example.com".to_owned()]
+            )
+        );
+    }
+}
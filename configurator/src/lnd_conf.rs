@@ -0,0 +1,395 @@
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// Section that lnd (go-flags) assigns to keys appearing before any `[section]` header.
+pub const DEFAULT_SECTION: &str = "Application Options";
+
+/// Keys whose values must never end up in the logs.
+const SECRET_KEYS: &[&str] = &["bitcoind.rpcpass", "db.postgres.dsn", "tor.password"];
+
+fn display_value<'a>(key: &str, value: &'a str) -> &'a str {
+    if SECRET_KEYS.contains(&key) {
+        "<redacted>"
+    } else {
+        value
+    }
+}
+
+/// In-memory representation of an `lnd.conf` file.
+///
+/// Sections and keys are kept in insertion order so that rendering is deterministic, and a key
+/// may appear more than once within a section (e.g. `neutrino.addpeer`). Like lnd (go-flags),
+/// section names and keys are matched case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LndConf {
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Section {
+    pub fn new(name: impl Into<String>) -> Self {
+        Section {
+            name: name.into(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets `key` to `value`, replacing every existing occurrence of `key` in this section.
+    pub fn set(&mut self, key: &str, value: impl Display) -> &mut Self {
+        let value = value.to_string();
+        match self
+            .entries
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            Some(idx) => {
+                self.entries[idx].1 = value;
+                let mut seen = false;
+                self.entries.retain(|(k, _)| {
+                    if !k.eq_ignore_ascii_case(key) {
+                        return true;
+                    }
+                    let first = !seen;
+                    seen = true;
+                    first
+                });
+            }
+            None => self.entries.push((key.to_owned(), value)),
+        }
+        self
+    }
+
+    /// Sets `key` only if `value` is `Some`.
+    pub fn set_opt(&mut self, key: &str, value: Option<impl Display>) -> &mut Self {
+        if let Some(value) = value {
+            self.set(key, value);
+        }
+        self
+    }
+
+    /// Appends another occurrence of `key`, keeping any existing ones.
+    pub fn add(&mut self, key: &str, value: impl Display) -> &mut Self {
+        self.entries.push((key.to_owned(), value.to_string()));
+        self
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl LndConf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the section called `name`, creating it at the end of the file if it does not exist
+    /// yet. Section names are matched case-insensitively, like lnd does.
+    pub fn section(&mut self, name: &str) -> &mut Section {
+        let idx = match self
+            .sections
+            .iter()
+            .position(|s| s.name.eq_ignore_ascii_case(name))
+        {
+            Some(idx) => idx,
+            None => {
+                self.sections.push(Section::new(name));
+                self.sections.len() - 1
+            }
+        };
+        &mut self.sections[idx]
+    }
+
+    pub fn has_section(&self, name: &str) -> bool {
+        self.sections
+            .iter()
+            .any(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The first section that sets `key`, if any.
+    pub fn section_with_key(&mut self, key: &str) -> Option<&mut Section> {
+        self.sections
+            .iter_mut()
+            .find(|s| s.entries.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)))
+    }
+
+    pub fn parse(input: &str) -> Result<Self, anyhow::Error> {
+        let mut conf = LndConf::new();
+        let mut current = DEFAULT_SECTION.to_owned();
+        for (idx, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                match header.strip_suffix(']') {
+                    Some(name) if !name.trim().is_empty() => {
+                        current = name.trim().to_owned();
+                        conf.section(&current);
+                    }
+                    _ => anyhow::bail!("lnd.conf line {}: malformed section header", idx + 1),
+                }
+                continue;
+            }
+            match line.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => {
+                    conf.section(&current).add(key.trim(), value.trim());
+                }
+                _ => anyhow::bail!("lnd.conf line {}: expected `key=value`", idx + 1),
+            }
+        }
+        Ok(conf)
+    }
+
+    /// Reads and parses the file at `path`, returning `None` if it does not exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, anyhow::Error> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => Ok(Some(Self::parse(&s)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Lists every key whose values differ between `self` (the old file) and `new`.
+    pub fn diff(&self, new: &LndConf) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (section, key) in self.keys().into_iter().chain(new.keys()) {
+            if changes.iter().any(|c: &Change| {
+                c.section.eq_ignore_ascii_case(&section) && c.key.eq_ignore_ascii_case(&key)
+            }) {
+                continue;
+            }
+            let old_values = self.values(&section, &key);
+            let new_values = new.values(&section, &key);
+            if old_values != new_values {
+                changes.push(Change {
+                    section,
+                    key,
+                    old: old_values,
+                    new: new_values,
+                });
+            }
+        }
+        changes
+    }
+
+    fn keys(&self) -> Vec<(String, String)> {
+        let mut keys: Vec<(String, String)> = Vec::new();
+        for section in &self.sections {
+            for (key, _) in &section.entries {
+                if !keys.iter().any(|(s, k)| {
+                    s.eq_ignore_ascii_case(&section.name) && k.eq_ignore_ascii_case(key)
+                }) {
+                    keys.push((section.name.clone(), key.clone()));
+                }
+            }
+        }
+        keys
+    }
+
+    fn values(&self, section: &str, key: &str) -> Vec<String> {
+        self.sections
+            .iter()
+            .filter(|s| s.name.eq_ignore_ascii_case(section))
+            .flat_map(|s| s.get_all(key).map(str::to_owned).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+impl Display for LndConf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, section) in self.sections.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", section.name)?;
+            for (key, value) in &section.entries {
+                writeln!(f, "{}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A key whose values differ between two `lnd.conf` files. An empty `old` means the key was
+/// added, an empty `new` means it was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub section: String,
+    pub key: String,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt_values = |values: &[String]| {
+            values
+                .iter()
+                .map(|v| display_value(&self.key, v))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (self.old.is_empty(), self.new.is_empty()) {
+            (true, _) => write!(
+                f,
+                "+ [{}] {}={}",
+                self.section,
+                self.key,
+                fmt_values(&self.new)
+            ),
+            (_, true) => write!(
+                f,
+                "- [{}] {}={}",
+                self.section,
+                self.key,
+                fmt_values(&self.old)
+            ),
+            _ => write!(
+                f,
+                "~ [{}] {}: {} -> {}",
+                self.section,
+                self.key,
+                fmt_values(&self.old),
+                fmt_values(&self.new)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = "\
+; written by hand
+externalip=203.0.113.7
+alias=my node
+
+[Bitcoind]
+# rpc credentials
+bitcoind.rpcuser=bitcoin
+bitcoind.rpcpass=secret
+
+[neutrino]
+neutrino.addpeer=a.example.com
+neutrino.addpeer=b.example.com
+";
+
+    #[test]
+    fn parses_sections_and_skips_comments() {
+        let mut conf = LndConf::parse(CONF).unwrap();
+        assert!(conf.has_section(DEFAULT_SECTION));
+        assert!(conf.has_section("bitcoind"));
+        let bitcoind = conf.section("BITCOIND");
+        assert_eq!(
+            bitcoind.get_all("bitcoind.rpcpass").collect::<Vec<_>>(),
+            ["secret"]
+        );
+        assert_eq!(
+            conf.section(DEFAULT_SECTION)
+                .get_all("alias")
+                .collect::<Vec<_>>(),
+            ["my node"]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_render() {
+        let conf = LndConf::parse(CONF).unwrap();
+        let rendered = conf.to_string();
+        assert_eq!(LndConf::parse(&rendered).unwrap(), conf);
+        assert!(rendered.starts_with("[Application Options]\nexternalip=203.0.113.7\n"));
+        assert!(!rendered.contains("rpc credentials"));
+    }
+
+    #[test]
+    fn keeps_repeated_keys() {
+        let mut conf = LndConf::parse(CONF).unwrap();
+        conf.section(DEFAULT_SECTION)
+            .add("externalip", "198.51.100.1")
+            .add("externalip", "198.51.100.2");
+        assert_eq!(
+            conf.section(DEFAULT_SECTION)
+                .get_all("externalip")
+                .collect::<Vec<_>>(),
+            ["203.0.113.7", "198.51.100.1", "198.51.100.2"]
+        );
+        assert_eq!(
+            conf.section("neutrino")
+                .get_all("neutrino.addpeer")
+                .collect::<Vec<_>>(),
+            ["a.example.com", "b.example.com"]
+        );
+    }
+
+    #[test]
+    fn set_replaces_every_occurrence() {
+        let mut conf = LndConf::parse(CONF).unwrap();
+        conf.section("neutrino")
+            .set("neutrino.addpeer", "c.example.com");
+        assert_eq!(
+            conf.section("neutrino")
+                .get_all("neutrino.addpeer")
+                .collect::<Vec<_>>(),
+            ["c.example.com"]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(LndConf::parse("[bitcoind\n").is_err());
+        assert!(LndConf::parse("[]\n").is_err());
+        assert!(LndConf::parse("no value\n").is_err());
+        assert!(LndConf::parse("=value\n").is_err());
+    }
+
+    #[test]
+    fn diffs_added_removed_and_changed_keys() {
+        let old = LndConf::parse(CONF).unwrap();
+        let mut new = old.clone();
+        new.section(DEFAULT_SECTION).set("alias", "renamed");
+        new.section("neutrino")
+            .add("neutrino.addpeer", "c.example.com");
+        new.section("tor").set("tor.active", true);
+        let mut removed = new.clone();
+        removed.sections.retain(|s| s.name != "Bitcoind");
+
+        let changes: Vec<String> = old.diff(&removed).iter().map(Change::to_string).collect();
+        assert_eq!(
+            changes,
+            [
+                "~ [Application Options] alias: my node -> renamed",
+                "- [Bitcoind] bitcoind.rpcuser=bitcoin",
+                "- [Bitcoind] bitcoind.rpcpass=<redacted>",
+                "~ [neutrino] neutrino.addpeer: a.example.com, b.example.com -> a.example.com, b.example.com, c.example.com",
+                "+ [tor] tor.active=true",
+            ]
+        );
+        assert!(old.diff(&old.clone()).is_empty());
+    }
+
+    #[test]
+    fn redacts_secret_keys() {
+        let old = LndConf::new();
+        let mut new = LndConf::new();
+        new.section("bitcoind").set("bitcoind.rpcpass", "hunter2");
+        new.section("db")
+            .set("db.postgres.dsn", "postgres://u:pw@host/db");
+        new.section("tor").set("tor.password", "hunter3");
+        let rendered: String = old.diff(&new).iter().map(|c| c.to_string()).collect();
+        assert!(!rendered.contains("hunter"), "{}", rendered);
+        assert!(!rendered.contains("pw@host"), "{}", rendered);
+        assert_eq!(rendered.matches("<redacted>").count(), 3);
+        // redaction only applies to the diff, lnd still gets the value
+        assert!(new.to_string().contains("bitcoind.rpcpass=hunter2"));
+    }
+}
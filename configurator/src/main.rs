@@ -1,202 +1,37 @@
-use base32::Alphabet;
-use bitcoincore_rpc::RpcApi;
+mod bitcoind;
+mod config;
+mod custom_conf;
+mod db;
+mod fees;
+mod generate;
+mod lnd_conf;
+mod nat;
+mod net;
+mod network;
+mod neutrino;
+mod presets;
+mod prune;
+mod render;
+mod tor;
+mod upgrade;
+mod validate;
+mod wallet;
+
 use rand::Rng;
-use serde_json::Value;
-use std::fs::File;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::Path;
-use std::process::Command;
-use std::str::FromStr;
-use std::{
-    io::{self, Read, Write},
-    time::Duration,
-};
-
-use serde::{ser::SerializeMap, Deserialize, Serialize, Serializer};
-
-fn parse_iface_ip(output: &str) -> Result<Option<&str>, anyhow::Error> {
-    let output = output.trim();
-    if output.is_empty() {
-        return Ok(None);
-    }
-    if let Some(ip) = output.split_ascii_whitespace().nth(3) {
-        Ok(Some(ip))
-    } else {
-        Err(anyhow::anyhow!("malformed output from `ip`"))
-    }
-}
-
-pub fn get_iface_ipv4_addr(iface: &str) -> Result<Option<Ipv4Addr>, anyhow::Error> {
-    Ok(parse_iface_ip(&String::from_utf8(
-        Command::new("ip")
-            .arg("-4")
-            .arg("-o")
-            .arg("addr")
-            .arg("show")
-            .arg(iface)
-            .output()?
-            .stdout,
-    )?)?
-    .map(|s| Ok::<_, anyhow::Error>(s.split("/").next().unwrap().parse()?))
-    .transpose()?)
-}
-
-fn pw_is_typeable(pw: &[u8]) -> bool {
-    pw.iter().all(|&byte| byte >= 32 && byte <= 126) // Space - ~
-}
-
-struct SkipNulls(Value);
-impl Serialize for SkipNulls {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        match &self.0 {
-            serde_json::Value::Object(map) => {
-                let mut map_serializer = serializer.serialize_map(Some(map.len()))?;
-                for (k, v) in map.into_iter().filter(|(_, v)| v != &&Value::Null) {
-                    map_serializer.serialize_entry(k, v)?;
-                }
-                map_serializer.end()
-            }
-            other => Value::serialize(other, serializer),
-        }
-    }
-}
-impl std::fmt::Display for SkipNulls {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", serde_json::to_string(self).unwrap())
-    }
-}
-
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct Config {
-    control_tor_address: String,
-    peer_tor_address: String,
-    watchtower_tor_address: String,
-    alias: Option<String>,
-    externalip: Option<String>,
-    color: String,
-    accept_keysend: bool,
-    accept_amp: bool,
-    reject_htlc: bool,
-    min_chan_size: Option<u64>,
-    max_chan_size: Option<u64>,
-    bitcoind: BitcoinCoreConfig,
-    autopilot: AutoPilotConfig,
-    watchtowers: WatchtowerConfig,
-    advanced: AdvancedConfig,
-    tor: TorConfig,
-}
+use std::time::Duration;
 
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct TorConfig {
-    use_tor_only: bool,
-    stream_isolation: bool,
-}
-#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
-#[serde(tag = "enabled")]
-#[serde(rename_all = "kebab-case")]
-enum WtClient {
-    #[serde(rename_all = "kebab-case")]
-    Disabled,
-    #[serde(rename_all = "kebab-case")]
-    Enabled { add_watchtowers: Vec<String> },
-}
-
-#[derive(Debug, Clone, serde::Deserialize)]
-#[serde(tag = "type")]
-#[serde(rename_all = "kebab-case")]
-struct WatchtowerConfig {
-    wt_server: bool,
-    wt_client: WtClient,
-}
-
-#[derive(Deserialize, Serialize)]
-struct TowerInfo {
-    pubkey: String,
-    listeners: Vec<String>,
-    uris: Vec<String>,
-}
-
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct BitcoinChannelConfig {
-    default_channel_confirmations: usize,
-    min_htlc: u64,
-    min_htlc_out: u64,
-    base_fee: u64,
-    fee_rate: u64,
-    time_lock_delta: usize,
-}
-
-#[derive(serde::Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct SweeperConfig {
-    sweeper_maxfeerate: u64,
-    sweeper_nodeadlineconftarget: usize,
-    sweeper_budget_tolocalratio: f64,
-    sweeper_budget_anchorcpfpratio: f64,
-    sweeper_budget_deadlinehtlcratio: f64,
-    sweeper_budget_nodeadlinehtlcratio: f64,
-}
-
-#[derive(serde::Deserialize, PartialEq, Eq)]
-#[serde(tag = "type")]
-#[serde(rename_all = "kebab-case")]
-enum BitcoinCoreConfig {
-    #[serde(rename_all = "kebab-case")]
-    None,
-    #[serde(rename_all = "kebab-case")]
-    Internal { user: String, password: String },
-}
-
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct AutoPilotConfig {
-    enabled: bool,
-    private: bool,
-    maxchannels: usize,
-    allocation: f64,       // %
-    min_channel_size: u64, // sats
-    max_channel_size: u64, // sats
-    advanced: AutoPilotAdvancedConfig,
-}
-
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct AutoPilotAdvancedConfig {
-    min_confirmations: usize,
-    confirmation_target: usize,
-}
-
-#[derive(Deserialize)]
-#[serde(rename_all = "kebab-case")]
-struct AdvancedConfig {
-    debug_level: String,
-    db_bolt_no_freelist_sync: bool,
-    db_bolt_auto_compact: bool,
-    db_bolt_auto_compact_min_age: u64,
-    db_bolt_db_timeout: u64,
-    recovery_window: Option<usize>,
-    payments_expiration_grace_period: usize,
-    default_remote_max_htlcs: usize,
-    max_channel_fee_allocation: f64,
-    max_commit_fee_rate_anchors: usize,
-    max_pending_channels: usize,
-    protocol_wumbo_channels: bool,
-    protocol_zero_conf: bool,
-    protocol_option_scid_alias: bool,
-    protocol_no_anchors: bool,
-    protocol_disable_script_enforced_lease: bool,
-    protocol_simple_taproot_chans: bool,
-    gc_canceled_invoices_on_startup: bool,
-    allow_circular_route: bool,
-    bitcoin: BitcoinChannelConfig,
-    sweeper: SweeperConfig,
-}
+use bitcoind::{
+    bitcoin_is_synced, bitcoin_rpc_is_ready, blockchain_info, check_zmq, preflight, BitcoindBackend,
+};
+use config::{BitcoinCoreConfig, Config, DbConfig, FeeSource, WtClient};
+use generate::RenderContext;
+use lnd_client::grpc::GrpcClient;
+use lnd_conf::LndConf;
+use net::WatchtowerUri;
+use prune::WalletHeights;
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Properties {
@@ -206,6 +41,7 @@ pub struct Properties {
 
 #[derive(serde::Serialize, serde::Deserialize)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum Data {
     LND {
         #[serde(rename = "LND Sync Height")]
@@ -242,11 +78,6 @@ pub struct Property<T> {
     masked: bool,
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
-pub struct CipherSeedMnemonic {
-    cipher_seed_mnemonic: Vec<String>,
-}
-
 fn get_alias(config: &Config) -> Result<String, anyhow::Error> {
     Ok(match &config.alias {
         // if it isn't defined in the config
@@ -276,8 +107,9 @@ fn reset_restore(base_path: &Path) -> Result<(), anyhow::Error> {
     std::fs::remove_file(path).map_err(From::from)
 }
 
-pub fn local_port_available(port: u16) -> Result<bool, anyhow::Error> {
-    match std::net::TcpListener::bind(("127.0.0.1", port)) {
+/// Whether nothing is listening on `ip:port` yet, so lnd will be able to bind it.
+pub fn local_port_available(ip: IpAddr, port: u16) -> Result<bool, anyhow::Error> {
+    match std::net::TcpListener::bind((ip, port)) {
         Ok(_) => Ok(true),
         Err(e) => {
             if e.kind() == std::io::ErrorKind::AddrInUse {
@@ -289,192 +121,212 @@ pub fn local_port_available(port: u16) -> Result<bool, anyhow::Error> {
     }
 }
 
-fn save_to_file(cipher_seed_mnemonic: &[String], file_path: &str) -> io::Result<()> {
-    let mut file = File::create(file_path)?;
-    for (i, word) in cipher_seed_mnemonic.iter().enumerate() {
-        writeln!(file, "{} {}", i + 1, word)?;
+/// Fails if another process already listens on one of the ports lnd is configured for.
+fn check_ports(config: &Config, listen_ips: &[IpAddr]) -> Result<(), anyhow::Error> {
+    let wildcard = IpAddr::from(Ipv4Addr::UNSPECIFIED);
+    let mut listeners = vec![("ports.peer", wildcard, config.ports.peer)];
+    for ip in listen_ips {
+        listeners.push(("ports.rpc", *ip, config.ports.rpc));
+        listeners.push(("ports.rest", *ip, config.ports.rest));
+    }
+    if config.watchtowers.wt_server {
+        listeners.push(("ports.watchtower", wildcard, config.ports.watchtower));
+    }
+    for (key, ip, port) in listeners {
+        if !local_port_available(ip, port)? {
+            anyhow::bail!(
+                "{} {} is already in use on {}, stop whatever else listens on it",
+                key,
+                port,
+                ip
+            );
+        }
     }
     Ok(())
 }
 
-struct WatchtowerUri {
-    pubkey: String,
-    address: String,
-}
-impl FromStr for WatchtowerUri {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split_input = s.split("@");
-        let pubkey = match split_input.next() {
-            Some(x) => x.to_string(),
-            None => anyhow::bail!("Couldn't parse the pubkey from watchtower URI"),
-        };
-        let address = match split_input.next() {
-            Some(x) => x.to_string(),
-            None => anyhow::bail!("Couldn't parse the address from watchtower URI"),
-        };
-        Ok(WatchtowerUri { pubkey, address })
+fn main() -> Result<(), anyhow::Error> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => run(),
+        Some("render") => render::render(&args[1..]),
+        Some(other) => Err(anyhow::anyhow!(
+            "unknown subcommand {:?}\n{}",
+            other,
+            render::USAGE
+        )),
     }
 }
 
-fn main() -> Result<(), anyhow::Error> {
+fn run() -> Result<(), anyhow::Error> {
+    // replaces the phase the previous run ended in, which the health check would report otherwise
+    wallet::set_phase(wallet::Phase::WaitingForLnd, None);
     while !Path::new("/root/.lnd/start9/config.yaml").exists() {
         std::thread::sleep(std::time::Duration::from_secs(1));
     }
-    let config: Config = serde_yaml::from_reader(File::open("/root/.lnd/start9/config.yaml")?)?;
+    let config = Config::load(Path::new("/root/.lnd/start9/config.yaml"))?;
     let alias = get_alias(&config)?;
-    let control_tor_address = config.control_tor_address;
-    let watchtower_tor_address = config.watchtower_tor_address;
-    let peer_tor_address = config.peer_tor_address;
-
     println!(
         "config fetched. alias = {:?}",
         config.alias.clone().unwrap_or("No alias found".to_owned())
     );
     println!("alias = {:?}", alias);
-    let mut outfile = File::create("/root/.lnd/lnd.conf")?;
 
-    let bitcoind_selected = match &config.bitcoind {
-        BitcoinCoreConfig::None => false,
-        _ => true,
-    };
+    network::check_network(config.network)?;
+    db::check_backend(&config.db, config.network)?;
+    if let DbConfig::Postgres { .. } = config.db {
+        println!("lnd's data lives in postgres, which is not part of this service's backups");
+    }
 
-    println!("bitcoind_selected = {}", bitcoind_selected);
+    let bitcoind = BitcoindBackend::from_config(&config.bitcoind, config.network);
+    let bitcoind_selected = bitcoind.is_some();
 
-    let (
-        bitcoind_rpc_user,
-        bitcoind_rpc_pass,
-        bitcoind_rpc_host,
-        bitcoind_rpc_port,
-        bitcoind_zmq_host,
-        bitcoind_zmq_block_port,
-        bitcoind_zmq_tx_port,
-    ) = match config.bitcoind {
-        BitcoinCoreConfig::None => (String::new(), String::new(), "", 0, "", 0, 0),
-        BitcoinCoreConfig::Internal { user, password } => (
-            user,
-            password,
-            "bitcoind.embassy",
-            8332,
-            "bitcoind.embassy",
-            28332,
-            28333,
-        ),
-    };
-
-    let rpc_info = &BitcoindRpcInfo {
-        host: &bitcoind_rpc_host,
-        port: bitcoind_rpc_port,
-        user: &bitcoind_rpc_user,
-        pass: &bitcoind_rpc_pass,
-    };
+    println!("bitcoind_selected = {}", bitcoind_selected);
 
     let mut bitcoin_synced = false;
-
-    if bitcoind_selected {
+    let mut rpc_polling = false;
+    // bitcoind's chain state when it is lnd's backend
+    let mut backend_chain = None;
+
+    if let Some(bitcoind) = &bitcoind {
+        if let Err(e) = preflight(bitcoind) {
+            if e.rpc_failed() && matches!(config.bitcoind, BitcoinCoreConfig::External { .. }) {
+                return Err(e.into());
+            }
+            println!("{}", e);
+        }
         loop {
-            if bitcoin_rpc_is_ready(rpc_info)? {
+            if bitcoin_rpc_is_ready(bitcoind)? {
                 break;
             }
             println!("Waiting for bitcoin RPC...");
             std::thread::sleep(std::time::Duration::from_secs(1));
         }
-        bitcoin_synced = bitcoin_is_synced(rpc_info)?;
+        let chain = blockchain_info(bitcoind)?;
+        bitcoin_synced = !chain.initial_block_download;
         println!("bitcoin_synced = {}", bitcoin_synced);
+        if let (true, Some(prune_height)) = (chain.pruned, chain.prune_height) {
+            println!(
+                "bitcoind is pruned: blocks below height {} are gone, {} MiB on disk",
+                prune_height,
+                chain.size_on_disk / (1024 * 1024)
+            );
+        }
+        if bitcoin_synced {
+            backend_chain = Some(chain);
+            let zmq_problems = check_zmq(bitcoind);
+            if !zmq_problems.is_empty() {
+                println!("lnd would not be notified of new blocks and transactions over ZMQ:");
+                for problem in &zmq_problems {
+                    println!("  - {}", problem);
+                }
+                println!("Falling back to polling bitcoind over RPC. Enable zmqpubrawblock and zmqpubrawtx on bitcoind to use ZMQ.");
+                rpc_polling = true;
+            }
+        }
     }
 
     let use_neutrino = !(bitcoind_selected && bitcoin_synced);
     println!("use_neutrino = {}", use_neutrino);
 
-    let container_ip = get_iface_ipv4_addr("eth0").unwrap_or_else(|e| {
-        eprintln!("{e}");
-        None
-    });
-
-    write!(
-        outfile,
-        include_str!("lnd.conf.template"),
-        container_ip = container_ip.unwrap_or_else(|| [0, 0, 0, 0].into()),
-        peer_tor_address = peer_tor_address,
-        watchtower_tor_address = watchtower_tor_address,
-        payments_expiration_grace_period = config.advanced.payments_expiration_grace_period,
-        debug_level = config.advanced.debug_level,
-        min_chan_size_row = match config.min_chan_size {
-            None => String::new(),
-            Some(u) => format!("minchansize={}", u),
-        },
-        max_chan_size_row = match config.max_chan_size {
-            None => String::new(),
-            Some(u) => format!("maxchansize={}", u),
-        },
-        default_remote_max_htlcs = config.advanced.default_remote_max_htlcs,
-        reject_htlc = config.reject_htlc,
-        max_channel_fee_allocation = config.advanced.max_channel_fee_allocation,
-        max_pending_channels = config.advanced.max_pending_channels,
-        max_commit_fee_rate_anchors = config.advanced.max_commit_fee_rate_anchors,
-        accept_keysend = config.accept_keysend,
-        accept_amp = config.accept_amp,
-        gc_canceled_invoices_on_startup = config.advanced.gc_canceled_invoices_on_startup,
-        allow_circular_route = config.advanced.allow_circular_route,
-        alias = alias,
-        externalip_row = match config.externalip {
-            None => String::new(),
-            Some(u) => format!("externalip={}", u),
-        },
-        color = config.color,
-        feeurl_row = if use_neutrino {
-            "fee.url=https://nodes.lightning.computer/fees/v1/btc-fee-estimates.json"
-        } else {
-            ""
+    let mut wallet_heights = WalletHeights::load()?;
+    let mut prune_problems = Vec::new();
+    if let Some(chain) = &backend_chain {
+        if config.advanced.recovery_window.is_some()
+            && prune::rescan_impossible(chain, &wallet_heights)
+        {
+            anyhow::bail!(
+                "refusing to start: recovery-window makes lnd rescan the wallet from height {}, but bitcoind is pruned up to height {}. Unset recovery-window or disable pruning on bitcoind.",
+                wallet_heights.birthday.unwrap_or_default(),
+                chain.prune_height.unwrap_or_default()
+            );
+        }
+        prune_problems = prune::pruned_heights(chain, &wallet_heights);
+        for problem in &prune_problems {
+            println!("warning: {}", problem);
+        }
+    }
+    prune::write_warning(&prune_problems)?;
+
+    let mut bitcoind_fees = false;
+    let mut bitcoind_neutrino_peer = false;
+    if use_neutrino {
+        if let (Some(bitcoind), true) = (&bitcoind, config.neutrino.connect.is_empty()) {
+            bitcoind_neutrino_peer = neutrino::bitcoind_serves_neutrino(bitcoind);
+        }
+        neutrino::preflight(&config)?;
+        if config.neutrino.fee_source == FeeSource::Bitcoind {
+            bitcoind_fees = bitcoind.as_ref().is_some_and(fees::bitcoind_can_estimate);
+            if !bitcoind_fees {
+                println!("bitcoind cannot estimate fees yet, using the default fee service");
+            }
+        }
+    }
+
+    let listen_ips = net::container_ips()?;
+    println!(
+        "rpc and rest will listen on {}",
+        listen_ips
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    check_ports(&config, &listen_ips)?;
+    if config.nat {
+        match nat::default_gateway().and_then(|gateway| {
+            nat::external_address(SocketAddr::new(gateway.into(), nat::NAT_PMP_PORT))
+        }) {
+            Ok(ip) => println!(
+                "NAT-PMP gateway reports external address {}, lnd will advertise it",
+                ip
+            ),
+            Err(e) => eprintln!(
+                "NAT-PMP discovery failed ({}), lnd will try UPnP and NAT-PMP itself",
+                e
+            ),
+        }
+    }
+    tor::preflight(&config.tor)?;
+    println!("Tor SOCKS proxy {} is up", config.tor.socks);
+
+    let mut lnd_conf = generate::lnd_conf(
+        &config,
+        &RenderContext {
+            alias: &alias,
+            listen_ips: listen_ips.clone(),
+            use_neutrino,
+            rpc_polling,
+            bitcoind_fees,
+            bitcoind_neutrino_peer,
+            bitcoind: bitcoind.as_ref(),
         },
-        bitcoin_node = if use_neutrino { "neutrino" } else { "bitcoind" },
-        bitcoin_default_chan_confs = config.advanced.bitcoin.default_channel_confirmations,
-        bitcoin_min_htlc = config.advanced.bitcoin.min_htlc,
-        bitcoin_min_htlc_out = config.advanced.bitcoin.min_htlc_out,
-        bitcoin_base_fee = config.advanced.bitcoin.base_fee,
-        bitcoin_fee_rate = config.advanced.bitcoin.fee_rate,
-        bitcoin_time_lock_delta = config.advanced.bitcoin.time_lock_delta,
-        bitcoind_rpc_user = bitcoind_rpc_user,
-        bitcoind_rpc_pass = bitcoind_rpc_pass,
-        bitcoind_rpc_host = bitcoind_rpc_host,
-        bitcoind_rpc_port = bitcoind_rpc_port,
-        bitcoind_zmq_host = bitcoind_zmq_host,
-        bitcoind_zmq_block_port = bitcoind_zmq_block_port,
-        bitcoind_zmq_tx_port = bitcoind_zmq_tx_port,
-        autopilot_enabled = config.autopilot.enabled,
-        autopilot_maxchannels = config.autopilot.maxchannels,
-        autopilot_allocation = config.autopilot.allocation / 100.0,
-        autopilot_min_channel_size = config.autopilot.min_channel_size,
-        autopilot_max_channel_size = config.autopilot.max_channel_size,
-        autopilot_private = config.autopilot.private,
-        autopilot_min_confirmations = config.autopilot.advanced.min_confirmations,
-        autopilot_confirmation_target = config.autopilot.advanced.confirmation_target,
-        protocol_wumbo_channels = config.advanced.protocol_wumbo_channels,
-        protocol_zero_conf = config.advanced.protocol_zero_conf,
-        protocol_option_scid_alias = config.advanced.protocol_option_scid_alias,
-        protocol_no_anchors = config.advanced.protocol_no_anchors,
-        protocol_disable_script_enforced_lease =
-            config.advanced.protocol_disable_script_enforced_lease,
-        protocol_simple_taproot_chans = config.advanced.protocol_simple_taproot_chans,
-        sweeper_maxfeerate = config.advanced.sweeper.sweeper_maxfeerate,
-        sweeper_nodeadlineconftarget = config.advanced.sweeper.sweeper_nodeadlineconftarget,
-        sweeper_budget_tolocalratio = config.advanced.sweeper.sweeper_budget_tolocalratio,
-        sweeper_budget_anchorcpfpratio = config.advanced.sweeper.sweeper_budget_anchorcpfpratio,
-        sweeper_budget_deadlinehtlcratio = config.advanced.sweeper.sweeper_budget_deadlinehtlcratio,
-        sweeper_budget_nodeadlinehtlcratio = config.advanced.sweeper.sweeper_budget_nodeadlinehtlcratio,
-        db_bolt_no_freelist_sync = config.advanced.db_bolt_no_freelist_sync,
-        db_bolt_auto_compact = config.advanced.db_bolt_auto_compact,
-        db_bolt_auto_compact_min_age = config.advanced.db_bolt_auto_compact_min_age,
-        db_bolt_db_timeout = config.advanced.db_bolt_db_timeout,
-        tor_enable_clearnet = !config.tor.use_tor_only,
-        tor_stream_isolation = config.tor.stream_isolation,
-        wt_server = config.watchtowers.wt_server,
-        wt_client = match config.watchtowers.wt_client {
-            WtClient::Disabled => false,
-            _ => true,
+    );
+    for warning in custom_conf::merge(&mut lnd_conf, &config)? {
+        println!("warning: {}", warning);
+    }
+    let lnd_conf_path = Path::new("/root/.lnd/lnd.conf");
+    match LndConf::load(lnd_conf_path) {
+        Ok(None) => println!("no previous lnd.conf found"),
+        Ok(Some(previous)) => {
+            let changes = previous.diff(&lnd_conf);
+            if changes.is_empty() {
+                println!("lnd.conf unchanged since last boot");
+            } else {
+                println!("lnd.conf changes since last boot:");
+                for change in changes {
+                    println!("  {}", change);
+                }
+            }
         }
-    )?;
+        Err(e) => eprintln!("could not read previous lnd.conf: {}", e),
+    }
+    println!("writing lnd.conf...");
+    std::fs::write("/root/.lnd/lnd.conf.tmp", lnd_conf.to_string())?;
+    std::fs::rename("/root/.lnd/lnd.conf.tmp", lnd_conf_path)?;
+
+    let chain_dir = config.network.chain_dir();
     let public_path = Path::new("/root/.lnd/public");
     // Create public directory to make accessible to dependents through the bindmounts interface
     println!("creating public directory...");
@@ -484,17 +336,24 @@ fn main() -> Result<(), anyhow::Error> {
     println!("writing .backupignore...");
     std::fs::write(
         Path::new("/root/.lnd/.backupignore.tmp"),
-        include_str!(".backupignore.template"),
+        generate::backup_ignore(config.network),
     )?;
     std::fs::rename("/root/.lnd/.backupignore.tmp", "/root/.lnd/.backupignore")?;
 
     // background configurator so lnd can start
     #[cfg(target_os = "linux")]
     nix::unistd::daemon(true, true)?;
-    let container_ip = container_ip.unwrap_or_else(|| [127, 0, 0, 1].into());
-    println!("checking port 10009 on {container_ip} (gRPC control port)...");
+    if let (true, Some(bitcoind)) = (bitcoind_fees, &bitcoind) {
+        println!(
+            "serving bitcoind fee estimates on {}",
+            fees::local_fee_url()
+        );
+        fees::spawn_server(bitcoind.clone())?;
+    }
+    let rpc_addr = SocketAddr::new(listen_ips[0], config.ports.rpc);
+    println!("checking {rpc_addr} (gRPC control port)...");
     loop {
-        if let Ok(_) = std::net::TcpStream::connect(SocketAddr::from((container_ip, 10009))) {
+        if std::net::TcpStream::connect(rpc_addr).is_ok() {
             break;
         } else {
             std::thread::sleep(std::time::Duration::from_secs(1));
@@ -504,17 +363,14 @@ fn main() -> Result<(), anyhow::Error> {
     println!("checking if we need to restore from channel backup...");
     let use_channel_backup_data = if is_restore(Path::new("/root/.lnd")) {
         println!("Detected Embassy Restore. Conducting precautionary channel backup restoration.");
-        let channel_backup_path = Path::new("/root/.lnd/data/chain/bitcoin/mainnet/channel.backup");
+        let channel_backup_path = chain_dir.join("channel.backup");
         if channel_backup_path.exists() {
             let bs = std::fs::read(channel_backup_path)?;
             // backup all except graph db
             // also delete graph db always
             // happen in backup action not in entrypoint
             std::fs::remove_dir_all("/root/.lnd/data/graph")?;
-            let encoded = base64::encode(bs);
-            Ok::<Option<Value>, std::io::Error>(Some(serde_json::json!({
-                "multi_chan_backup": encoded
-            })))
+            Ok::<Option<Vec<u8>>, std::io::Error>(Some(bs))
         } else {
             println!("No channel restoration required. No channel backup exists.");
             Ok(None)
@@ -523,247 +379,39 @@ fn main() -> Result<(), anyhow::Error> {
         Ok(None)
     }?;
 
+    let grpc = GrpcClient::new(
+        "lnd.embassy",
+        config.ports.rpc,
+        lnd_client::TLS_CERT_PATH,
+        lnd_client::DEFAULT_TIMEOUT,
+    )?;
+    let recovery_window = config
+        .advanced
+        .recovery_window
+        .map(i32::try_from)
+        .transpose()?;
     println!("unlocking wallet...");
-    if Path::new("/root/.lnd/pwd.dat").exists() {
-        let password_bytes = std::fs::read("/root/.lnd/pwd.dat")?;
-        let pw_typeable = pw_is_typeable(&password_bytes);
-        let status;
-        if !pw_typeable {
-            let base_32_pw = base32::encode(Alphabet::RFC4648 { padding: false }, &password_bytes);
-            status = {
-                use std::process;
-                let mut res;
-                let stat;
-                loop {
-                    std::thread::sleep(Duration::from_secs(5));
-                    let cmd = process::Command::new("curl")
-                        .arg("--no-progress-meter")
-                        .arg("-X")
-                        .arg("POST")
-                        .arg("--cacert")
-                        .arg("/root/.lnd/tls.cert")
-                        .arg("https://lnd.embassy:8080/v1/changepassword")
-                        .arg("-d")
-                        .arg(serde_json::to_string(&SkipNulls(serde_json::json!({
-                            "current_password": base64::encode(&password_bytes),
-                            "new_password": base64::encode(&base_32_pw),
-                        })))?)
-                        .stdin(process::Stdio::piped())
-                        .stdout(process::Stdio::piped())
-                        .stderr(process::Stdio::piped())
-                        .spawn()?;
-                    res = cmd.wait_with_output()?;
-                    let output = String::from_utf8(res.stdout)?.parse::<Value>()?;
-                    match output.as_object() {
-                        None => {
-                            stat = Err(anyhow::anyhow!(
-                                "Invalid output from changepassword attempt: {:?}",
-                                output
-                            ));
-                            break;
-                        }
-                        Some(o) => match o.get("message") {
-                            None => {
-                                stat = Ok(output);
-                                std::fs::write("/root/.lnd/new_pwd.dat", &base_32_pw)?;
-                                std::fs::rename("/root/.lnd/new_pwd.dat", "/root/.lnd/pwd.dat")?;
-                                println!("Wallet password successfully converted to base32");
-                                break;
-                            }
-                            Some(v) => match v.as_str() {
-                                None => {
-                                    stat = Err(anyhow::anyhow!(
-                                        "Invalid error output from changepassword attempt: {:?}",
-                                        v
-                                    ));
-                                    break;
-                                }
-                                Some(s) => {
-                                    if s.contains("waiting to start") {
-                                        continue;
-                                    } else {
-                                        stat = Err(anyhow::anyhow!("{}", s));
-                                        break;
-                                    }
-                                }
-                            },
-                        },
-                    }
+    // wallet unlocking has to happen while LND running (encrypted on disk) creds are stored in separate place on disk (pwd.dat in our case - in data volume)
+    let use_channel_backup_data = match wallet::start(&grpc, recovery_window)? {
+        wallet::Wallet::Opened => use_channel_backup_data,
+        wallet::Wallet::Created => {
+            if let Some(chain) = &backend_chain {
+                wallet_heights.set_birthday(chain.blocks);
+                if let Err(e) = wallet_heights.save() {
+                    eprintln!("Failed to record the wallet birthday: {}", e);
                 }
-                stat
-            };
-        } else {
-            status = {
-                use std::process;
-                let mut res;
-                let stat;
-                loop {
-                    std::thread::sleep(Duration::from_secs(5));
-                    let cmd = process::Command::new("curl")
-                        .arg("--no-progress-meter")
-                        .arg("-X")
-                        .arg("POST")
-                        .arg("--cacert")
-                        .arg("/root/.lnd/tls.cert")
-                        .arg("https://lnd.embassy:8080/v1/unlockwallet")
-                        .arg("-d")
-                        .arg(serde_json::to_string(&SkipNulls(serde_json::json!({
-                            "wallet_password": base64::encode(&password_bytes),
-                            "recovery_window": config.advanced.recovery_window,
-                        })))?)
-                        .stdin(process::Stdio::piped())
-                        .stdout(process::Stdio::piped())
-                        .stderr(process::Stdio::piped())
-                        .spawn()?;
-                    res = cmd.wait_with_output()?;
-                    let output = String::from_utf8(res.stdout)?.parse::<Value>()?;
-                    match output.as_object() {
-                        None => {
-                            stat = Err(anyhow::anyhow!(
-                                "Invalid output from wallet unlock attempt: {:?}",
-                                output
-                            ));
-                            break;
-                        }
-                        Some(o) => match o.get("message") {
-                            None => {
-                                stat = Ok(output);
-                                break;
-                            }
-                            Some(v) => match v.as_str() {
-                                None => {
-                                    stat = Err(anyhow::anyhow!(
-                                        "Invalid error output from wallet unlock attempt: {:?}",
-                                        v
-                                    ));
-                                    break;
-                                }
-                                Some(s) => {
-                                    if s.contains("waiting to start") {
-                                        continue;
-                                    } else {
-                                        stat = Err(anyhow::anyhow!("{}", s));
-                                        break;
-                                    }
-                                }
-                            },
-                        },
-                    }
-                }
-                stat
-            };
-        }
-        match status {
-            Err(e) => {
-                eprintln!("{}", e);
-                return Err(anyhow::anyhow!("Error unlocking wallet. Exiting."));
             }
-            // wallet unlocking has to happen while LND running (encrypted on disk) creds are stored in separate place on disk (pwd.dat in our case - in data volume)
-            Ok(_) => match use_channel_backup_data {
-                None => (),
-                Some(_backups) => loop {
-                    std::thread::sleep(Duration::from_secs(5));
-                    let output = Command::new("lncli")
-                        .arg("--rpcserver=lnd.embassy")
-                        .arg("restorechanbackup")
-                        .arg("--multi_file")
-                        .arg("/root/.lnd/data/chain/bitcoin/mainnet/channel.backup")
-                        .output();
-                    match output {
-                        Ok(output) if output.status.success() => {
-                            println!("SCB recovery initiated.");
-                            reset_restore(Path::new("/root/.lnd"))?;
-                            break;
-                        }
-                        Ok(output) => {
-                            let stderr = String::from_utf8_lossy(&output.stderr);
-                            if stderr.contains("server is still in the process of starting") {
-                                continue;
-                            } else {
-                                eprintln!("Error initiating SCB recovery: {}", stderr);
-                                return Err(anyhow::anyhow!("{}", stderr));
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to run lncli: {}", e);
-                            return Err(anyhow::anyhow!("{}", e));
-                        }
-                    }
-                },
-            },
+            None
         }
-    } else {
-        let mut cipher_seed_created = false;
-        println!("creating password data");
-        let password_bytes = {
-            let mut buf = [0; 16];
-            File::open("/dev/random")?.read_exact(&mut buf)?;
-            base32::encode(Alphabet::RFC4648 { padding: false }, &buf).into_bytes()
-        };
-        let file_path = "/root/.lnd/start9/cipherSeedMnemonic.txt";
-
-        while !cipher_seed_created {
-            let output = std::process::Command::new("curl")
-                .arg("--no-progress-meter")
-                .arg("-X")
-                .arg("GET")
-                .arg("--cacert")
-                .arg("/root/.lnd/tls.cert")
-                .arg("https://lnd.embassy:8080/v1/genseed")
-                .arg("-d")
-                .arg(format!("{}", serde_json::json!({})))
-                .output()?;
-            if !output.status.success() {
-                eprintln!("{}", std::str::from_utf8(&output.stderr)?);
-                return Err(anyhow::anyhow!("Error generating seed. Exiting."));
-            }
-
-            if let Ok(CipherSeedMnemonic {
-                cipher_seed_mnemonic,
-            }) = serde_json::from_slice(&output.stdout)
-            {
-                println!("CipherSeed successfully generated");
-
-                if let Err(err) = save_to_file(&cipher_seed_mnemonic, file_path) {
-                    eprintln!("Failed to save the CipherSeedMnemonic: {}", err);
-                } else {
-                    println!("CipherSeedMnemonic saved to '{}'", file_path);
-                }
-
-                let status = std::process::Command::new("curl")
-                    .arg("--no-progress-meter")
-                    .arg("-X")
-                    .arg("POST")
-                    .arg("--cacert")
-                    .arg("/root/.lnd/tls.cert")
-                    .arg("https://lnd.embassy:8080/v1/initwallet")
-                    .arg("-d")
-                    .arg(format!(
-                        "{}",
-                        serde_json::json!({
-                            "wallet_password": base64::encode(&password_bytes),
-                            "cipher_seed_mnemonic": cipher_seed_mnemonic,
-                        })
-                    ))
-                    .status()?;
-                if status.success() {
-                    std::fs::write("/root/.lnd/pwd.dat", &password_bytes)?;
-                } else {
-                    return Err(anyhow::anyhow!("Error creating wallet. Exiting."));
-                }
-                cipher_seed_created = true
-            } else {
-                println!("Waiting for RPC to start...");
-                std::thread::sleep(Duration::from_secs(5));
-            }
-        }
-    }
+    };
+    network::record_network(config.network)?;
+    db::record_backend(&config.db)?;
 
     println!("copying macaroon to public dir...");
-    while !Path::new("/root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon").exists() {
+    while !chain_dir.join("admin.macaroon").exists() {
         std::thread::sleep(std::time::Duration::from_secs(1));
     }
-    for macaroon in std::fs::read_dir("/root/.lnd/data/chain/bitcoin/mainnet")? {
+    for macaroon in std::fs::read_dir(&chain_dir)? {
         let macaroon = macaroon?;
         if macaroon.path().extension().and_then(|s| s.to_str()) == Some("macaroon") {
             std::fs::copy(
@@ -773,101 +421,105 @@ fn main() -> Result<(), anyhow::Error> {
         }
     }
 
+    let grpc = grpc.with_macaroon(chain_dir.join("admin.macaroon"))?;
+    if let Some(multi_chan_backup) = use_channel_backup_data {
+        if let Err(e) = grpc.restore_channel_backups(multi_chan_backup) {
+            eprintln!("Error initiating SCB recovery: {}", e);
+            return Err(e.into());
+        }
+        println!("SCB recovery initiated.");
+        reset_restore(Path::new("/root/.lnd"))?;
+    }
+
+    if let Some(chain) = &backend_chain {
+        match wallet_heights.refresh_channels(&grpc) {
+            Ok(()) => {
+                if let Err(e) = wallet_heights.save() {
+                    eprintln!("Failed to record channel funding heights: {}", e);
+                }
+                let problems = prune::pruned_heights(chain, &wallet_heights);
+                if let Err(e) = prune::write_warning(&problems) {
+                    eprintln!("Failed to write prune warning: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Failed to look up channel funding heights: {}", e),
+        }
+    }
+
     match config.watchtowers.wt_server {
         false => {
             println!("Watchtower Server disabled");
-            if let Err(_) = std::fs::remove_file("/root/.lnd/start9/towerServerUrl") {
+            if std::fs::remove_file("/root/.lnd/start9/towerServerUrl").is_err() {
                 println!("The towerServerUrl file does not exist or cannot be deleted.");
             } else {
                 println!("The towerServerUrl file has been deleted successfully.");
             }
         }
         true => loop {
-            let output = Command::new("lncli")
-                .arg("--rpcserver=lnd.embassy")
-                .arg("tower")
-                .arg("info")
-                .output();
-            match output {
-                Ok(output) if output.status.success() => {
-                    println!("Tower server {:?} started", &output);
-                    let tower_info_response = String::from_utf8_lossy(&output.stdout);
-                    let tower_server: TowerInfo = serde_json::from_str(&tower_info_response)
-                        .expect("Failed to parse Tower Info JSON response");
-                    let result =
-                        std::fs::write("/root/.lnd/start9/towerServerUrl", &tower_server.uris[0]);
-                    match result {
-                        Ok(_) => {
-                            println!("Tower {} written towerServerUrl", &tower_server.uris[0]);
-                        }
-                        Err(err) => {
-                            println!("Error writing Tower server to Properties: {}", err);
+            match grpc.tower_info() {
+                Ok(tower_info) => {
+                    match tower_info.uris.first() {
+                        Some(uri) => {
+                            match std::fs::write("/root/.lnd/start9/towerServerUrl", uri) {
+                                Ok(_) => println!("Tower {} written towerServerUrl", uri),
+                                Err(err) => {
+                                    println!("Error writing Tower server to Properties: {}", err)
+                                }
+                            }
                         }
+                        None => println!("Tower server started without a public URI"),
                     }
                     break;
                 }
-                Ok(output) => {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    println!("Failed to retreive tower info with error: {}", stderr);
-                    std::thread::sleep(Duration::from_secs(10));
-                }
-                Err(_) => {
-                    println!("Error running the command: lncli --rpcserver=lnd.embassy tower info");
+                Err(e) => {
+                    println!("Failed to retreive tower info with error: {}", e);
                     std::thread::sleep(Duration::from_secs(10));
                 }
             }
         },
     }
 
-    if true {
-        match config.watchtowers.wt_client {
-            WtClient::Disabled => {
-                println!("Watchtower Client Disabled");
-            }
-            WtClient::Enabled { add_watchtowers } => {
-                for watchtower_uri in add_watchtowers.iter() {
-                    let parsed_watchtower_uri: WatchtowerUri = watchtower_uri.parse()?;
-                    loop {
-                        println!("Configuring Watchtower for {}... ", alias);
-                        println!(
-                            "pubkey: {} || host: {}",
-                            &parsed_watchtower_uri.pubkey, &parsed_watchtower_uri.address
-                        );
-                        let output = Command::new("lncli")
-                            .arg("--rpcserver=lnd.embassy")
-                            .arg("wtclient")
-                            .arg("add")
-                            .arg(&watchtower_uri)
-                            .output();
-                        println!("The lncli command ran for {}", &watchtower_uri);
-                        match output {
-                            Ok(output) if output.status.success() => {
-                                println!("Added watchtower {}.", &watchtower_uri);
-                                break;
-                            }
-                            Ok(output) => {
-                                let stderr = String::from_utf8_lossy(&output.stderr);
-                                println!(
-                                    "Failed to add watchtower {} with error: {}",
-                                    &watchtower_uri, stderr
-                                );
-                                std::thread::sleep(Duration::from_secs(10));
-                            }
-                            Err(_) => {
-                                println!("Error running the command: lncli --rpcserver=lnd.embassy wtclient add {}.", &watchtower_uri);
-                                std::thread::sleep(Duration::from_secs(10));
-                            }
+    match config.watchtowers.wt_client {
+        WtClient::Disabled => {
+            println!("Watchtower Client Disabled");
+        }
+        WtClient::Enabled { add_watchtowers } => {
+            for watchtower_uri in add_watchtowers.iter() {
+                // checked by validate::validate
+                let parsed_watchtower_uri = WatchtowerUri::parse(watchtower_uri)
+                    .map_err(|e| anyhow::anyhow!("{}: {}", watchtower_uri, e))?;
+                loop {
+                    println!("Configuring Watchtower for {}... ", alias);
+                    println!(
+                        "pubkey: {} || host: {}",
+                        hex::encode(&parsed_watchtower_uri.pubkey),
+                        parsed_watchtower_uri.address
+                    );
+                    match grpc.add_tower(
+                        parsed_watchtower_uri.pubkey.clone(),
+                        &parsed_watchtower_uri.address.to_string(),
+                    ) {
+                        Ok(()) => {
+                            println!("Added watchtower {}.", &watchtower_uri);
+                            break;
+                        }
+                        Err(e) => {
+                            println!(
+                                "Failed to add watchtower {} with error: {}",
+                                &watchtower_uri, e
+                            );
+                            std::thread::sleep(Duration::from_secs(10));
                         }
                     }
                 }
             }
         }
-    };
+    }
 
-    if bitcoind_selected {
+    if let Some(bitcoind) = &bitcoind {
         println!("looping forever to see if we need to switch backends...");
         loop {
-            let bitcoin_synced = match bitcoin_is_synced(rpc_info) {
+            let bitcoin_synced = match bitcoin_is_synced(bitcoind) {
                 Ok(bs) => bs,
                 Err(e) => {
                     println!("Error checking whether bitcoin is synced: {:?}", e);
@@ -892,42 +544,3 @@ fn main() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
-
-#[derive(Serialize, Deserialize)]
-struct JsonRpc1Res {
-    result: serde_json::Value,
-    error: Option<BitcoindError>,
-    id: serde_json::Value,
-}
-#[derive(Debug, Serialize, Deserialize)]
-struct BitcoindError {
-    code: i32,
-    message: String,
-}
-
-#[derive(Debug)]
-struct BitcoindRpcInfo<'a> {
-    host: &'a str,
-    port: u16,
-    user: &'a str,
-    pass: &'a str,
-}
-
-fn bitcoin_rpc_is_ready(rpc_info: &BitcoindRpcInfo) -> Result<bool, anyhow::Error> {
-    let rpc_client = bitcoincore_rpc::Client::new(
-        &*format!("http://{}:{}", rpc_info.host, rpc_info.port),
-        bitcoincore_rpc::Auth::UserPass(rpc_info.user.to_owned(), rpc_info.pass.to_owned()),
-    )?;
-    Ok(rpc_client.get_best_block_hash().is_ok())
-}
-
-fn bitcoin_is_synced(rpc_info: &BitcoindRpcInfo) -> Result<bool, anyhow::Error> {
-    let rpc_client = bitcoincore_rpc::Client::new(
-        &*format!("http://{}:{}", rpc_info.host, rpc_info.port),
-        bitcoincore_rpc::Auth::UserPass(rpc_info.user.to_owned(), rpc_info.pass.to_owned()),
-    )?;
-    match rpc_client.get_blockchain_info() {
-        Ok(bir) => Ok(!bir.initial_block_download),
-        Err(e) => Err(anyhow::anyhow!("Bitcoin RPC Error {:?}", e)),
-    }
-}
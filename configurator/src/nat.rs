@@ -0,0 +1,124 @@
+//! With `nat` enabled lnd asks the default gateway for the external address over UPnP or NAT-PMP
+//! (RFC 6886) and only logs a failure. We ask the gateway over NAT-PMP ourselves first so the
+//! address lnd will advertise, or why there is none, shows up in the startup log.
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// Port NAT-PMP gateways listen on.
+pub const NAT_PMP_PORT: u16 = 5351;
+
+/// RFC 6886 retransmits after 250ms and doubles the wait every time; three tries keep the
+/// startup delay under two seconds for gateways without NAT-PMP.
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(250);
+const TRIES: u32 = 3;
+
+/// The IPv4 default gateway from the kernel routing table.
+pub fn default_gateway() -> Result<Ipv4Addr, anyhow::Error> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+    for route in routes.lines().skip(1) {
+        let fields: Vec<&str> = route.split_whitespace().collect();
+        if let [_, "00000000", gateway, ..] = fields[..] {
+            // the kernel prints the network order address as a native endian integer
+            let gateway = u32::from_str_radix(gateway, 16)?;
+            return Ok(Ipv4Addr::from(gateway.to_ne_bytes()));
+        }
+    }
+    anyhow::bail!("no default route")
+}
+
+/// Asks the NAT-PMP gateway at `gateway` for its external IPv4 address.
+pub fn external_address(gateway: SocketAddr) -> Result<Ipv4Addr, anyhow::Error> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect(gateway)?;
+    let mut timeout = INITIAL_TIMEOUT;
+    let mut response = [0; 16];
+    for _ in 0..TRIES {
+        // version 0, opcode 0: external address request
+        socket.send(&[0, 0])?;
+        socket.set_read_timeout(Some(timeout))?;
+        match socket.recv(&mut response) {
+            Ok(len) => return parse_response(&response[..len]),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                timeout *= 2
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    anyhow::bail!("{} did not answer NAT-PMP requests", gateway)
+}
+
+fn parse_response(response: &[u8]) -> Result<Ipv4Addr, anyhow::Error> {
+    match response {
+        // version, opcode 128 + 0, result code, seconds since epoch, address
+        [0, 128, r0, r1, _, _, _, _, a, b, c, d] => match u16::from_be_bytes([*r0, *r1]) {
+            0 => Ok(Ipv4Addr::new(*a, *b, *c, *d)),
+            1 => anyhow::bail!("the gateway does not support NAT-PMP version 0"),
+            2 => anyhow::bail!("NAT-PMP is disabled on the gateway"),
+            3 => anyhow::bail!("the gateway has no external address (network failure)"),
+            code => anyhow::bail!("the gateway answered with NAT-PMP result code {}", code),
+        },
+        _ => anyhow::bail!("malformed NAT-PMP response {:02x?}", response),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A local stand-in for a NAT-PMP gateway that answers every external address request with
+    /// `result` and `address`.
+    fn gateway(result: u16, address: Ipv4Addr) -> SocketAddr {
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut request = [0; 16];
+            while let Ok((len, from)) = socket.recv_from(&mut request) {
+                if request[..len] != [0, 0] {
+                    continue;
+                }
+                let mut response = vec![0, 128];
+                response.extend_from_slice(&result.to_be_bytes());
+                response.extend_from_slice(&7u32.to_be_bytes());
+                response.extend_from_slice(&address.octets());
+                socket.send_to(&response, from).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn reports_external_address() {
+        let gateway = gateway(0, Ipv4Addr::new(203, 0, 113, 7));
+        assert_eq!(
+            external_address(gateway).unwrap(),
+            Ipv4Addr::new(203, 0, 113, 7)
+        );
+    }
+
+    #[test]
+    fn reports_gateway_errors() {
+        let gateway = gateway(2, Ipv4Addr::UNSPECIFIED);
+        let err = external_address(gateway).unwrap_err().to_string();
+        assert!(err.contains("disabled"), "{}", err);
+    }
+
+    #[test]
+    fn gives_up_on_silent_gateway() {
+        // bound but never answering, like a router without NAT-PMP
+        let silent = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let err = external_address(silent.local_addr().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("did not answer"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        assert!(parse_response(&[0, 128, 0]).is_err());
+        assert!(parse_response(&[0, 129, 0, 0, 0, 0, 0, 7, 203, 0, 113, 7]).is_err());
+    }
+}
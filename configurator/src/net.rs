@@ -0,0 +1,364 @@
+use std::fmt::{self, Display};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+
+use nix::ifaddrs::getifaddrs;
+use nix::net::if_::InterfaceFlags;
+use nix::sys::socket::SockAddr;
+use sha3::{Digest, Sha3_256};
+use std::time::Duration;
+
+/// A `host`, `host:port`, `ip`, `ip:port` or `[ipv6]:port` as accepted by lnd.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostPort {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl HostPort {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| format!("unterminated '[' in {:?}", s))?;
+            let port = match rest {
+                "" => None,
+                _ => Some(
+                    rest.strip_prefix(':')
+                        .ok_or_else(|| format!("unexpected {:?} after ']' in {:?}", rest, s))?,
+                ),
+            };
+            host.parse::<std::net::Ipv6Addr>()
+                .map_err(|_| format!("{:?} is not an IPv6 address", host))?;
+            (host, port)
+        } else if s.parse::<std::net::Ipv6Addr>().is_ok() {
+            (s, None)
+        } else {
+            match s.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (s, None),
+            }
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in {:?}", s));
+        }
+        if host.parse::<IpAddr>().is_err() && !is_hostname(host) {
+            return Err(format!(
+                "{:?} is neither an IP address nor a hostname",
+                host
+            ));
+        }
+        let port = port
+            .map(|p| match p.parse::<u16>() {
+                Ok(0) | Err(_) => Err(format!("invalid port {:?} in {:?}", p, s)),
+                Ok(p) => Ok(p),
+            })
+            .transpose()?;
+        Ok(HostPort {
+            host: host.to_owned(),
+            port,
+        })
+    }
+
+    pub fn is_onion(&self) -> bool {
+        self.host.ends_with(".onion")
+    }
+
+    pub fn port_or(&self, default: u16) -> u16 {
+        self.port.unwrap_or(default)
+    }
+}
+
+impl Display for HostPort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bracket = self.host.contains(':');
+        match (bracket, self.port) {
+            (true, Some(port)) => write!(f, "[{}]:{}", self.host, port),
+            (false, Some(port)) => write!(f, "{}:{}", self.host, port),
+            (_, None) => f.write_str(&self.host),
+        }
+    }
+}
+
+/// A `pubkey@host[:port]` watchtower URI, as `lncli wtclient add` takes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchtowerUri {
+    /// Compressed secp256k1 public key of the tower.
+    pub pubkey: Vec<u8>,
+    pub address: HostPort,
+}
+
+impl WatchtowerUri {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (pubkey, address) = s
+            .split_once('@')
+            .ok_or_else(|| format!("expected pubkey@host:port, got {:?}", s))?;
+        if pubkey.len() != 66 || !pubkey.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "{:?} is not a public key: expected 66 hex characters",
+                pubkey
+            ));
+        }
+        Ok(WatchtowerUri {
+            pubkey: hex::decode(pubkey).map_err(|e| e.to_string())?,
+            address: HostPort::parse(address)?,
+        })
+    }
+}
+
+/// RFC 1123 hostname: dot separated labels of letters, digits and inner hyphens.
+pub fn is_hostname(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    !host.is_empty()
+        && host.len() <= 253
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Checks that `host` is a Tor v3 onion address: 56 base32 characters encoding a 32 byte ed25519
+/// public key, a 2 byte checksum and the version byte 3, followed by `.onion` (rend-spec-v3).
+pub fn check_onion_v3(host: &str) -> Result<(), String> {
+    let encoded = host
+        .strip_suffix(".onion")
+        .ok_or_else(|| format!("{:?} does not end in .onion", host))?;
+    if encoded.len() != 56 {
+        return Err(format!(
+            "{:?} is not a v3 onion address: expected 56 characters before .onion, got {}",
+            host,
+            encoded.len()
+        ));
+    }
+    let decoded = base32::decode(base32::Alphabet::RFC4648 { padding: false }, encoded)
+        .ok_or_else(|| format!("{:?} is not a v3 onion address: invalid base32", host))?;
+    let (pubkey, rest) = decoded.split_at(32);
+    let (checksum, version) = (&rest[..2], rest[2]);
+    if version != 3 {
+        return Err(format!(
+            "{:?} is not a v3 onion address: version byte is {}",
+            host, version
+        ));
+    }
+    let expected = Sha3_256::new()
+        .chain_update(b".onion checksum")
+        .chain_update(pubkey)
+        .chain_update([version])
+        .finalize();
+    if checksum != &expected[..2] {
+        return Err(format!(
+            "{:?} is not a v3 onion address: checksum mismatch, check for typos",
+            host
+        ));
+    }
+    Ok(())
+}
+
+/// Tries to open a TCP connection to `host:port`, returning why it failed otherwise.
+pub fn tcp_reachable(host: &str, port: u16, timeout: Duration) -> Result<(), String> {
+    let addrs = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?;
+    let mut last_err = format!("{} did not resolve to any address", host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(_) => return Ok(()),
+            Err(e) => last_err = format!("{}: {}", addr, e),
+        }
+    }
+    Err(last_err)
+}
+
+/// Host and port an `http://` or `https://` URL points at.
+pub fn http_url_host_port(url: &str) -> Result<HostPort, String> {
+    let (rest, default_port) = if let Some(rest) = url.strip_prefix("https://") {
+        (rest, 443)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (rest, 80)
+    } else {
+        return Err(format!("{:?} must start with http:// or https://", url));
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let mut host_port = HostPort::parse(authority)?;
+    host_port.port = Some(host_port.port_or(default_port));
+    Ok(host_port)
+}
+
+/// Addresses of this container lnd's RPC listeners can bind to: the first IPv4 and the first IPv6
+/// address of any interface that is up, skipping loopback and IPv6 link-local addresses (which
+/// need a zone to bind to). IPv4 comes first.
+pub fn container_ips() -> Result<Vec<IpAddr>, anyhow::Error> {
+    let mut ipv4 = None;
+    let mut ipv6 = None;
+    let mut skipped = Vec::new();
+    for iface in getifaddrs()? {
+        let ip = match iface.address {
+            Some(SockAddr::Inet(addr)) => addr.ip().to_std(),
+            _ => continue,
+        };
+        let up = iface.flags.contains(InterfaceFlags::IFF_UP);
+        let loopback = iface.flags.contains(InterfaceFlags::IFF_LOOPBACK) || ip.is_loopback();
+        let link_local = matches!(ip, IpAddr::V6(v6) if v6.segments()[0] & 0xffc0 == 0xfe80);
+        if !up || loopback || link_local {
+            skipped.push(format!("{} {}", iface.interface_name, ip));
+            continue;
+        }
+        match ip {
+            IpAddr::V4(_) => ipv4 = ipv4.or(Some(ip)),
+            IpAddr::V6(_) => ipv6 = ipv6.or(Some(ip)),
+        }
+    }
+    let ips: Vec<IpAddr> = ipv4.into_iter().chain(ipv6).collect();
+    if ips.is_empty() {
+        anyhow::bail!(
+            "no usable IPv4 or IPv6 address on any network interface (skipped: {})",
+            if skipped.is_empty() {
+                "none found".to_owned()
+            } else {
+                skipped.join(", ")
+            }
+        );
+    }
+    Ok(ips)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONION: &str = "6sfczgaiqlnaa6nqtckq5n4w2da7tatxzzcrwiwjrmvqdnnga6zpxjqd.onion";
+
+    fn host_port(host: &str, port: Option<u16>) -> HostPort {
+        HostPort {
+            host: host.to_owned(),
+            port,
+        }
+    }
+
+    /// An onion with a valid checksum for `version`, to get past the checksum check.
+    fn onion_with_version(version: u8) -> String {
+        let pubkey = [7; 32];
+        let checksum = Sha3_256::new()
+            .chain_update(b".onion checksum")
+            .chain_update(pubkey)
+            .chain_update([version])
+            .finalize();
+        let mut bytes = pubkey.to_vec();
+        bytes.extend_from_slice(&checksum[..2]);
+        bytes.push(version);
+        format!(
+            "{}.onion",
+            base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes).to_lowercase()
+        )
+    }
+
+    #[test]
+    fn accepts_v3_onions() {
+        assert_eq!(check_onion_v3(ONION), Ok(()));
+        assert_eq!(check_onion_v3(&onion_with_version(3)), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_onions() {
+        let typo = ONION.replacen('6', "7", 1);
+        let e = check_onion_v3(&typo).unwrap_err();
+        assert!(e.contains("checksum mismatch"), "{}", e);
+
+        let e = check_onion_v3(&onion_with_version(2)).unwrap_err();
+        assert!(e.contains("version byte is 2"), "{}", e);
+
+        // v2 onions are 16 characters and no longer supported by Tor
+        let e = check_onion_v3("expyuzz4wqqyqhjn.onion").unwrap_err();
+        assert!(e.contains("expected 56 characters"), "{}", e);
+
+        let e = check_onion_v3(&ONION.replace('a', "1")).unwrap_err();
+        assert!(e.contains("invalid base32"), "{}", e);
+
+        assert!(check_onion_v3(ONION.trim_end_matches(".onion")).is_err());
+    }
+
+    #[test]
+    fn parses_host_and_port() {
+        assert_eq!(
+            HostPort::parse("node.example.com:9736"),
+            Ok(host_port("node.example.com", Some(9736)))
+        );
+        assert_eq!(
+            HostPort::parse(" 203.0.113.7 "),
+            Ok(host_port("203.0.113.7", None))
+        );
+        assert_eq!(HostPort::parse(ONION), Ok(host_port(ONION, None)));
+        assert!(HostPort::parse(ONION).unwrap().is_onion());
+    }
+
+    #[test]
+    fn parses_ipv6() {
+        assert_eq!(
+            HostPort::parse("2001:db8::1"),
+            Ok(host_port("2001:db8::1", None))
+        );
+        assert_eq!(
+            HostPort::parse("[2001:db8::1]"),
+            Ok(host_port("2001:db8::1", None))
+        );
+        let parsed = HostPort::parse("[2001:db8::1]:9735").unwrap();
+        assert_eq!(parsed, host_port("2001:db8::1", Some(9735)));
+        assert_eq!(parsed.to_string(), "[2001:db8::1]:9735");
+    }
+
+    #[test]
+    fn rejects_bad_host_ports() {
+        for bad in [
+            "",
+            ":9735",
+            "node.example.com:0",
+            "node.example.com:65536",
+            "node.example.com:port",
+            "bad_host:9735",
+            "[2001:db8::1",
+            "[2001:db8::1]9735",
+            "[node.example.com]:9735",
+        ] {
+            assert!(HostPort::parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn parses_watchtower_uris() {
+        let pubkey = format!("02{}", "ab".repeat(32));
+        let uri = WatchtowerUri::parse(&format!("{}@[2001:db8::1]:9911", pubkey)).unwrap();
+        assert_eq!(uri.pubkey, hex::decode(&pubkey).unwrap());
+        assert_eq!(uri.address, host_port("2001:db8::1", Some(9911)));
+        assert_eq!(uri.address.to_string(), "[2001:db8::1]:9911");
+        assert_eq!(
+            WatchtowerUri::parse(&format!("{}@{}", pubkey, ONION))
+                .unwrap()
+                .address,
+            host_port(ONION, None)
+        );
+        for bad in [
+            "tower.example.com:9911".to_owned(),
+            format!("{}@", pubkey),
+            format!("{}@tower.example.com@other", pubkey),
+            format!("{}@tower.example.com", &pubkey[..64]),
+        ] {
+            assert!(WatchtowerUri::parse(&bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn defaults_url_ports_by_scheme() {
+        assert_eq!(
+            http_url_host_port("https://fees.example.com/v1/fees?x=1"),
+            Ok(host_port("fees.example.com", Some(443)))
+        );
+        assert_eq!(
+            http_url_host_port("http://[::1]:8099/fees"),
+            Ok(host_port("::1", Some(8099)))
+        );
+        assert!(http_url_host_port("ftp://fees.example.com").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! lnd keeps a wallet and channel database per network, but the wallet password (`pwd.dat`) and
+//! what the configurator records next to it (the seed backup, the database backend and the
+//! heights a pruned bitcoind must keep) exist once per service. We remember which network they
+//! belong to and refuse to switch away from it, instead of failing later on a wallet that the
+//! stored password does not unlock.
+
+use std::path::Path;
+
+use crate::config::Network;
+
+const LND_DIR: &str = "/root/.lnd";
+
+/// Network the wallet in `/root/.lnd` belongs to, relative to it. Written by [`record_network`]
+/// once lnd's server is up on it.
+const MARKER: &str = "start9/network";
+
+/// Network of the wallet in `lnd_dir`, if there is one. Wallets created before the network could
+/// be selected are on mainnet.
+fn existing_network(lnd_dir: &Path) -> Result<Option<String>, anyhow::Error> {
+    match std::fs::read_to_string(lnd_dir.join(MARKER)) {
+        Ok(network) => return Ok(Some(network.trim().to_owned())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(lnd_dir
+        .join("pwd.dat")
+        .exists()
+        .then(|| Network::Mainnet.as_str().to_owned()))
+}
+
+/// Fails if this service already holds a wallet for a different network than the selected one.
+pub fn check_network(network: Network) -> Result<(), anyhow::Error> {
+    check(Path::new(LND_DIR), network)
+}
+
+fn check(lnd_dir: &Path, network: Network) -> Result<(), anyhow::Error> {
+    match existing_network(lnd_dir)? {
+        Some(existing) if existing != network.as_str() => anyhow::bail!(
+            "refusing to switch from {existing} to {network}: the wallet password, seed backup and \
+             database backend stored by this service belong to the {existing} wallet, and LND \
+             keeps only one of each. Select {existing} again. LND can only run on {network} after \
+             a fresh install, which starts over without the {existing} wallet and channels."
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Records the selected network as the one the wallet belongs to. Like
+/// [`crate::db::record_backend`], only called once lnd's server is active on it.
+pub fn record_network(network: Network) -> Result<(), anyhow::Error> {
+    record(Path::new(LND_DIR), network)
+}
+
+fn record(lnd_dir: &Path, network: Network) -> Result<(), anyhow::Error> {
+    let marker = lnd_dir.join(MARKER);
+    if marker.exists() {
+        return Ok(());
+    }
+    println!("recording {} as the wallet's network", network);
+    std::fs::write(marker, network.as_str())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, record};
+    use crate::config::Network;
+
+    fn lnd_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("start9")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sticks_to_the_recorded_network() {
+        let dir = lnd_dir();
+        check(dir.path(), Network::Signet).unwrap();
+        check(dir.path(), Network::Mainnet).unwrap();
+
+        record(dir.path(), Network::Signet).unwrap();
+        check(dir.path(), Network::Signet).unwrap();
+        let err = check(dir.path(), Network::Mainnet).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("refusing to switch from signet to mainnet:"));
+
+        // later starts keep the first network
+        record(dir.path(), Network::Mainnet).unwrap();
+        check(dir.path(), Network::Mainnet).unwrap_err();
+    }
+
+    #[test]
+    fn existing_wallets_are_on_mainnet() {
+        let dir = lnd_dir();
+        std::fs::write(dir.path().join("pwd.dat"), "password").unwrap();
+        check(dir.path(), Network::Mainnet).unwrap();
+        check(dir.path(), Network::Testnet).unwrap_err();
+    }
+}
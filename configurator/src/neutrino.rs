@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::bitcoind::BitcoindBackend;
+use crate::config::{Config, FeeSource};
+use crate::net::{http_url_host_port, tcp_reachable, HostPort};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probes the configured neutrino peers and fee service before lnd is started on neutrino.
+///
+/// Onion peers are skipped since they are only reachable through lnd's Tor proxy. Unreachable
+/// `add-peers` are only reported, since lnd still has the DNS seeds to fall back on, but lnd
+/// cannot sync without any of its `connect` peers, nor start without its fee service.
+pub fn preflight(config: &Config) -> Result<(), anyhow::Error> {
+    let default_port = config.network.p2p_port();
+    let probe = |peer: &str| -> Result<(), String> {
+        let peer = HostPort::parse(peer)?;
+        if peer.is_onion() {
+            println!(
+                "neutrino peer {} is only reachable over Tor, not checking",
+                peer
+            );
+            return Ok(());
+        }
+        tcp_reachable(&peer.host, peer.port_or(default_port), PROBE_TIMEOUT)
+    };
+
+    for peer in &config.neutrino.add_peers {
+        if let Err(e) = probe(peer) {
+            println!("neutrino peer {} is unreachable: {}", peer, e);
+        }
+    }
+
+    let neutrino = &config.neutrino;
+    if !neutrino.connect.is_empty() {
+        let mut errors = Vec::new();
+        for peer in &neutrino.connect {
+            match probe(peer) {
+                Ok(()) => break,
+                Err(e) => errors.push(format!("{}: {}", peer, e)),
+            }
+        }
+        if errors.len() == neutrino.connect.len() {
+            anyhow::bail!(
+                "none of the neutrino.connect peers is reachable:\n  - {}",
+                errors.join("\n  - ")
+            );
+        }
+    }
+
+    if let FeeSource::Url { url } = &neutrino.fee_source {
+        let target = http_url_host_port(url).map_err(|e| anyhow::anyhow!(e))?;
+        if let Err(e) = tcp_reachable(&target.host, target.port_or(443), PROBE_TIMEOUT) {
+            anyhow::bail!("neutrino.fee-source.url {} is unreachable: {}", url, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether neutrino can add `bitcoind` next to its public peers, which keeps part of its queries
+/// off the public network.
+///
+/// lnd only runs on neutrino while bitcoind is in IBD, and Core ignores `getheaders` during IBD,
+/// so bitcoind is added as a `neutrino.addpeer` rather than pinned as the only peer.
+pub fn bitcoind_serves_neutrino(bitcoind: &BitcoindBackend) -> bool {
+    match bitcoind.serves_compact_filters() {
+        Ok(true) => (),
+        Ok(false) => {
+            println!("bitcoind does not serve compact block filters (blockfilterindex=1 and peerblockfilters=1 are required), using public neutrino peers");
+            return false;
+        }
+        Err(e) => {
+            println!(
+                "could not check whether bitcoind serves compact block filters, using public neutrino peers: {}",
+                e
+            );
+            return false;
+        }
+    }
+    match tcp_reachable(&bitcoind.rpc_host, bitcoind.p2p_port, PROBE_TIMEOUT) {
+        Ok(()) => {
+            println!(
+                "bitcoind serves compact block filters, adding {} next to the public neutrino peers",
+                bitcoind.p2p_address()
+            );
+            true
+        }
+        Err(e) => {
+            println!(
+                "bitcoind serves compact block filters but its P2P port is unreachable, using public neutrino peers: {}",
+                e
+            );
+            false
+        }
+    }
+}
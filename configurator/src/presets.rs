@@ -0,0 +1,245 @@
+//! Named presets that fill coherent values for a kind of node across `advanced`, `advanced.bitcoin`
+//! and `advanced.sweeper`. The config form leaves the keys presets set empty until the user picks
+//! a value, so a value the user picked always wins, even one equal to the default. Keys left
+//! unset are taken from the preset, or from [`defaults`] without one.
+
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+/// `(key path, value)` pairs in `config.yaml`'s layout.
+type Values = Vec<(&'static str, Value)>;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Forwards payments for others: large channels, many pending opens, circular rebalancing.
+    RoutingNode,
+    /// Receives payments for a shop: no spontaneous payments, few and non-dust HTLCs.
+    Merchant,
+    /// Backs a mobile wallet: spontaneous payments and zero-conf channels to the phone.
+    MobileBackend,
+}
+
+impl Preset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::RoutingNode => "routing-node",
+            Preset::Merchant => "merchant",
+            Preset::MobileBackend => "mobile-backend",
+        }
+    }
+
+    fn values(&self) -> Values {
+        match self {
+            Preset::RoutingNode => vec![
+                ("advanced.protocol-wumbo-channels", true.into()),
+                ("advanced.allow-circular-route", true.into()),
+                ("advanced.max-pending-channels", 10.into()),
+                ("advanced.default-remote-max-htlcs", 483.into()),
+                ("advanced.gc-canceled-invoices-on-startup", true.into()),
+                ("advanced.bitcoin.min-htlc", 1.into()),
+                ("advanced.bitcoin.min-htlc-out", 1.into()),
+                ("advanced.bitcoin.base-fee", 1000.into()),
+                ("advanced.bitcoin.fee-rate", 100.into()),
+                ("advanced.bitcoin.time-lock-delta", 80.into()),
+                ("advanced.sweeper.sweeper-maxfeerate", 1000.into()),
+            ],
+            Preset::Merchant => vec![
+                ("accept-keysend", false.into()),
+                ("accept-amp", false.into()),
+                ("advanced.allow-circular-route", false.into()),
+                ("advanced.max-pending-channels", 5.into()),
+                ("advanced.default-remote-max-htlcs", 30.into()),
+                ("advanced.gc-canceled-invoices-on-startup", true.into()),
+                ("advanced.bitcoin.min-htlc", 1000.into()),
+                ("advanced.bitcoin.min-htlc-out", 1000.into()),
+                ("advanced.bitcoin.default-channel-confirmations", 3.into()),
+            ],
+            Preset::MobileBackend => vec![
+                ("accept-keysend", true.into()),
+                ("accept-amp", true.into()),
+                ("advanced.allow-circular-route", false.into()),
+                ("advanced.max-pending-channels", 10.into()),
+                ("advanced.protocol-option-scid-alias", true.into()),
+                ("advanced.protocol-zero-conf", true.into()),
+                ("advanced.gc-canceled-invoices-on-startup", true.into()),
+                ("advanced.bitcoin.min-htlc", 1.into()),
+                ("advanced.bitcoin.min-htlc-out", 1.into()),
+            ],
+        }
+    }
+}
+
+/// Keys presets set, with their value when neither the user nor a preset picked one. The config
+/// form leaves these keys unset (numbers empty, booleans at [`UNSET`]) and has no defaults of its
+/// own for them, so this is where their defaults live.
+pub fn defaults() -> Values {
+    vec![
+        ("accept-keysend", true.into()),
+        ("accept-amp", false.into()),
+        ("advanced.protocol-wumbo-channels", false.into()),
+        ("advanced.protocol-option-scid-alias", false.into()),
+        ("advanced.protocol-zero-conf", false.into()),
+        ("advanced.allow-circular-route", false.into()),
+        ("advanced.max-pending-channels", 5.into()),
+        ("advanced.default-remote-max-htlcs", 483.into()),
+        ("advanced.gc-canceled-invoices-on-startup", false.into()),
+        ("advanced.bitcoin.default-channel-confirmations", 3.into()),
+        ("advanced.bitcoin.min-htlc", 1.into()),
+        ("advanced.bitcoin.min-htlc-out", 1000.into()),
+        ("advanced.bitcoin.base-fee", 1000.into()),
+        ("advanced.bitcoin.fee-rate", 1.into()),
+        ("advanced.bitcoin.time-lock-delta", 40.into()),
+        ("advanced.sweeper.sweeper-maxfeerate", 1000.into()),
+    ]
+}
+
+/// How the config form stores an unset boolean, since StartOS booleans cannot be null. Chosen
+/// ones are stored as `enabled` or `disabled`.
+pub const UNSET: &str = "default";
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |value, key| value.as_mapping()?.get(&key.into()))
+}
+
+fn insert(value: &mut Value, path: &str, new: Value) {
+    if let Value::Mapping(map) = value {
+        match path.split_once('.') {
+            None => {
+                map.insert(path.into(), new);
+            }
+            Some((key, rest)) => insert(
+                map.entry(key.into())
+                    .or_insert_with(|| Value::Mapping(Mapping::new())),
+                rest,
+                new,
+            ),
+        }
+    }
+}
+
+/// Fills every key of [`defaults`] the user left unset in `config` from its `preset`, or with its
+/// default, and turns the form's `enabled`/`disabled` into booleans. Returns the preset and the
+/// values it set.
+pub fn apply(config: &mut Value) -> Result<Option<(Preset, Values)>, anyhow::Error> {
+    let preset: Option<Preset> = match config.get("preset") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(name)) if name == "none" => None,
+        Some(preset) => Some(
+            serde_yaml::from_value(preset.clone())
+                .map_err(|e| anyhow::anyhow!("invalid preset: {}", e))?,
+        ),
+    };
+    let preset_values = preset.map(|preset| preset.values()).unwrap_or_default();
+    let mut applied = Vec::new();
+    for (path, default) in defaults() {
+        let chosen = match lookup(config, path) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(value)) if value == UNSET => None,
+            Some(Value::String(value)) if value == "enabled" => Some(true.into()),
+            Some(Value::String(value)) if value == "disabled" => Some(false.into()),
+            Some(value) => Some(value.clone()),
+        };
+        let value = match (chosen, preset_values.iter().find(|(key, _)| *key == path)) {
+            (Some(value), _) => value,
+            (None, Some((_, value))) => {
+                applied.push((path, value.clone()));
+                value.clone()
+            }
+            (None, None) => default,
+        };
+        insert(config, path, value);
+    }
+    Ok(preset.map(|preset| (preset, applied)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_to(yaml: &str) -> (Value, Vec<&'static str>) {
+        let mut config: Value = serde_yaml::from_str(yaml).unwrap();
+        let applied = apply(&mut config).unwrap();
+        let keys = applied
+            .map(|(_, values)| values.into_iter().map(|(key, _)| key).collect())
+            .unwrap_or_default();
+        (config, keys)
+    }
+
+    #[test]
+    fn fills_unset_keys_but_not_chosen_ones() {
+        // as written by the form: fee-rate and base-fee chosen, the latter equal to its default
+        let (config, applied) = apply_to(
+            "
+preset: routing-node
+accept-keysend: default
+advanced:
+  max-pending-channels: ~
+  protocol-wumbo-channels: default
+  allow-circular-route: disabled
+  bitcoin: {fee-rate: 250, base-fee: 1000, min-htlc: ~}
+",
+        );
+        assert!(applied.contains(&"advanced.max-pending-channels"));
+        assert!(!applied.contains(&"advanced.bitcoin.fee-rate"));
+        assert!(!applied.contains(&"advanced.allow-circular-route"));
+        assert_eq!(config["advanced"]["max-pending-channels"], Value::from(10));
+        assert_eq!(
+            config["advanced"]["protocol-wumbo-channels"],
+            Value::from(true)
+        );
+        assert_eq!(
+            config["advanced"]["allow-circular-route"],
+            Value::from(false)
+        );
+        assert_eq!(config["advanced"]["bitcoin"]["fee-rate"], Value::from(250));
+        assert_eq!(config["advanced"]["bitcoin"]["base-fee"], Value::from(1000));
+        // missing keys are filled in too, and keys the preset leaves alone get their default
+        assert_eq!(
+            config["advanced"]["bitcoin"]["time-lock-delta"],
+            Value::from(80)
+        );
+        assert_eq!(config["accept-keysend"], Value::from(true));
+        assert_eq!(config["advanced"]["protocol-zero-conf"], Value::from(false));
+    }
+
+    #[test]
+    fn without_preset_fills_defaults() {
+        let (config, applied) = apply_to(
+            "
+accept-amp: enabled
+advanced: {max-pending-channels: 7, protocol-zero-conf: true, bitcoin: {time-lock-delta: ~}}
+",
+        );
+        assert!(applied.is_empty());
+        assert_eq!(config["accept-amp"], Value::from(true));
+        assert_eq!(config["accept-keysend"], Value::from(true));
+        assert_eq!(config["advanced"]["max-pending-channels"], Value::from(7));
+        assert_eq!(config["advanced"]["protocol-zero-conf"], Value::from(true));
+        assert_eq!(
+            config["advanced"]["bitcoin"]["time-lock-delta"],
+            Value::from(40)
+        );
+    }
+
+    #[test]
+    fn every_preset_key_has_a_default() {
+        let defaults = defaults();
+        for preset in [Preset::RoutingNode, Preset::Merchant, Preset::MobileBackend].iter() {
+            for (path, _) in preset.values() {
+                assert!(defaults.iter().any(|(key, _)| *key == path), "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn none_and_missing_apply_nothing() {
+        for yaml in ["{}", "preset: ~", "preset: none"].iter() {
+            let mut config: Value = serde_yaml::from_str(yaml).unwrap();
+            assert!(apply(&mut config).unwrap().is_none());
+        }
+        let mut config: Value = serde_yaml::from_str("preset: exchange").unwrap();
+        assert!(apply(&mut config).is_err());
+    }
+}
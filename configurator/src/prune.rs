@@ -0,0 +1,204 @@
+//! A pruned bitcoind cannot serve blocks below its prune height, so lnd fails to rescan from a
+//! wallet birthday or re-fetch a channel funding block that lies below it. We keep track of those
+//! heights across boots and warn (or refuse to start) before lnd runs into it.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use lnd_client::grpc::GrpcClient;
+use serde::{Deserialize, Serialize};
+
+use crate::bitcoind::BlockchainInfo;
+
+const HEIGHTS_PATH: &str = "/root/.lnd/start9/walletHeights.yaml";
+/// Read by the `prune` health check.
+const WARNING_PATH: &str = "/root/.lnd/start9/pruneWarning.txt";
+
+/// btcwallet starts its birthday rescan about two days of blocks before the seed was created.
+const BIRTHDAY_MARGIN: u64 = 288;
+
+/// Block heights lnd may need to fetch from bitcoind again.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WalletHeights {
+    /// Height lnd's wallet rescans from, only known for wallets created by the configurator while
+    /// bitcoind was the backend.
+    pub birthday: Option<u64>,
+    /// Funding height of each open channel, by channel point.
+    #[serde(default)]
+    pub channel_funding: BTreeMap<String, u64>,
+}
+
+impl WalletHeights {
+    pub fn load() -> Result<Self, anyhow::Error> {
+        match std::fs::read(HEIGHTS_PATH) {
+            Ok(bytes) => Ok(serde_yaml::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let tmp = format!("{}.tmp", HEIGHTS_PATH);
+        std::fs::write(&tmp, serde_yaml::to_string(self)?)?;
+        std::fs::rename(&tmp, HEIGHTS_PATH)?;
+        Ok(())
+    }
+
+    /// Records the birthday of a wallet created while bitcoind was at `tip`.
+    pub fn set_birthday(&mut self, tip: u64) {
+        self.birthday = Some(tip.saturating_sub(BIRTHDAY_MARGIN));
+    }
+
+    /// Replaces the channel funding heights with those of lnd's currently open channels.
+    pub fn refresh_channels(&mut self, lnd: &GrpcClient) -> Result<(), anyhow::Error> {
+        let mut channel_funding = BTreeMap::new();
+        for channel in lnd.list_channels()? {
+            // the short channel id packs the funding height into its upper 24 bits
+            channel_funding.insert(channel.channel_point, channel.chan_id >> 40);
+        }
+        self.channel_funding = channel_funding;
+        Ok(())
+    }
+}
+
+/// Describes every height lnd may need that bitcoind has already pruned.
+pub fn pruned_heights(chain: &BlockchainInfo, heights: &WalletHeights) -> Vec<String> {
+    let prune_height = match (chain.pruned, chain.prune_height) {
+        (true, Some(prune_height)) => prune_height,
+        _ => return Vec::new(),
+    };
+    let mut problems = Vec::new();
+    if let Some(birthday) = heights.birthday.filter(|&h| h < prune_height) {
+        problems.push(format!(
+            "the wallet birthday (height {}) is below the prune height {}, so a wallet rescan would fail",
+            birthday, prune_height
+        ));
+    }
+    for (channel_point, height) in &heights.channel_funding {
+        if *height < prune_height {
+            problems.push(format!(
+                "channel {} was funded at height {}, below the prune height {}",
+                channel_point, height, prune_height
+            ));
+        }
+    }
+    problems
+}
+
+/// Whether a wallet rescan, as triggered by `recovery-window`, would start below the prune height.
+pub fn rescan_impossible(chain: &BlockchainInfo, heights: &WalletHeights) -> bool {
+    match (chain.pruned, chain.prune_height, heights.birthday) {
+        (true, Some(prune_height), Some(birthday)) => birthday < prune_height,
+        _ => false,
+    }
+}
+
+/// Writes the warning the `prune` health check reports, or clears it if there are no problems.
+pub fn write_warning(problems: &[String]) -> Result<(), anyhow::Error> {
+    write(Path::new(WARNING_PATH), problems)
+}
+
+fn write(warning_path: &Path, problems: &[String]) -> Result<(), anyhow::Error> {
+    if problems.is_empty() {
+        if warning_path.exists() {
+            std::fs::remove_file(warning_path)?;
+        }
+        return Ok(());
+    }
+    std::fs::write(
+        warning_path,
+        format!(
+            "Bitcoin Core is pruned below heights LND may need: {}. Disable pruning on bitcoind, which re-downloads the chain, so LND can fetch them.",
+            problems.join("; ")
+        ),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pruned_heights, rescan_impossible, write, WalletHeights};
+    use crate::bitcoind::BlockchainInfo;
+
+    fn chain(pruned: bool, prune_height: Option<u64>) -> BlockchainInfo {
+        BlockchainInfo {
+            blocks: 800_000,
+            initial_block_download: false,
+            pruned,
+            prune_height,
+            size_on_disk: 0,
+        }
+    }
+
+    fn heights(birthday: Option<u64>, channels: &[(&str, u64)]) -> WalletHeights {
+        WalletHeights {
+            birthday,
+            channel_funding: channels
+                .iter()
+                .map(|(point, height)| ((*point).to_owned(), *height))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn heights_at_the_prune_height_are_still_available() {
+        let chain = chain(true, Some(700_000));
+        let available = heights(Some(700_000), &[("a:0", 700_000), ("b:1", 750_000)]);
+        assert!(pruned_heights(&chain, &available).is_empty());
+        assert!(!rescan_impossible(&chain, &available));
+
+        let pruned = heights(Some(699_999), &[("a:0", 699_999), ("b:1", 700_000)]);
+        assert_eq!(
+            pruned_heights(&chain, &pruned),
+            [
+                "the wallet birthday (height 699999) is below the prune height 700000, so a wallet rescan would fail",
+                "channel a:0 was funded at height 699999, below the prune height 700000",
+            ]
+        );
+        assert!(rescan_impossible(&chain, &pruned));
+    }
+
+    #[test]
+    fn unpruned_or_unknown_heights_are_fine() {
+        let low = heights(Some(0), &[("a:0", 0)]);
+        for chain in [
+            chain(false, None),
+            chain(false, Some(700_000)),
+            chain(true, None),
+        ] {
+            assert!(pruned_heights(&chain, &low).is_empty());
+            assert!(!rescan_impossible(&chain, &low));
+        }
+        // a wallet without a known birthday may still have pruned channels
+        let chain = chain(true, Some(700_000));
+        let no_birthday = heights(None, &[("a:0", 1)]);
+        assert_eq!(pruned_heights(&chain, &no_birthday).len(), 1);
+        assert!(!rescan_impossible(&chain, &no_birthday));
+    }
+
+    #[test]
+    fn birthday_keeps_a_margin_below_the_tip() {
+        let mut heights = WalletHeights::default();
+        heights.set_birthday(800_000);
+        assert_eq!(heights.birthday, Some(799_712));
+        heights.set_birthday(100);
+        assert_eq!(heights.birthday, Some(0));
+    }
+
+    #[test]
+    fn writes_and_clears_the_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pruneWarning.txt");
+        write(&path, &[]).unwrap();
+        assert!(!path.exists());
+
+        write(&path, &["first".to_owned(), "second".to_owned()]).unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("Bitcoin Core is pruned below heights LND may need: first; second. "));
+
+        write(&path, &[]).unwrap();
+        assert!(!path.exists());
+    }
+}
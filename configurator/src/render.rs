@@ -0,0 +1,97 @@
+//! `configurator render`: prints the files the configurator would write for a given config.yaml
+//! without waiting on, contacting or writing to anything.
+//!
+//! Since nothing is probed, the printed lnd.conf differs from the one a real start writes in:
+//! - `rpclisten`/`restlisten`: `0.0.0.0` instead of each of the container's addresses.
+//! - `alias`: a placeholder when config.yaml sets none, instead of the alias generated and kept
+//!   on first boot.
+//! - `bitcoin.node`, `bitcoind.rpcpolling` and the ZMQ keys: follow `--backend` instead of
+//!   bitcoind's IBD state and ZMQ publishers.
+//! - `fee.url`: with `neutrino.fee-source: bitcoind` always the network's public fee service,
+//!   since whether bitcoind can estimate fees is not checked.
+//! - `neutrino.addpeer`: bitcoind is only added with `--compact-filters`, instead of when it
+//!   serves compact block filters on a reachable P2P port.
+//!
+//! Everything else, custom-lnd-conf included, is generated by the same code as a real start,
+//! see the golden files in `src/golden/`.
+
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use crate::bitcoind::BitcoindBackend;
+use crate::config::Config;
+use crate::custom_conf;
+use crate::generate::{self, RenderContext};
+
+pub const USAGE: &str = "usage: configurator render <config.yaml> [--backend bitcoind|bitcoind-polling|neutrino] [--compact-filters]";
+
+/// Stand-in for the alias the real run generates and persists on first boot.
+const DEFAULT_ALIAS_PLACEHOLDER: &str = "start9-<generated on first boot>";
+
+/// Backend state to simulate, since the real one depends on bitcoind's IBD status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SimulatedBackend {
+    /// bitcoind is reachable and done with IBD.
+    Bitcoind,
+    /// bitcoind is done with IBD but does not publish the ZMQ notifications lnd needs.
+    BitcoindPolling,
+    /// bitcoind is unavailable or in IBD, so lnd falls back to neutrino.
+    Neutrino,
+}
+
+pub fn render(args: &[String]) -> Result<(), anyhow::Error> {
+    let mut config_path = None;
+    let mut backend = SimulatedBackend::Bitcoind;
+    let mut compact_filters = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => {
+                backend = match args.next().map(String::as_str) {
+                    Some("bitcoind") => SimulatedBackend::Bitcoind,
+                    Some("bitcoind-polling") => SimulatedBackend::BitcoindPolling,
+                    Some("neutrino") => SimulatedBackend::Neutrino,
+                    other => anyhow::bail!("invalid --backend {:?}\n{}", other, USAGE),
+                }
+            }
+            "--compact-filters" => compact_filters = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            path if config_path.is_none() && !path.starts_with('-') => config_path = Some(path),
+            other => anyhow::bail!("unexpected argument {:?}\n{}", other, USAGE),
+        }
+    }
+    let config_path = config_path.ok_or_else(|| anyhow::anyhow!("{}", USAGE))?;
+    let config = Config::load(Path::new(config_path))?;
+
+    let bitcoind = BitcoindBackend::from_config(&config.bitcoind, config.network);
+    let alias = config
+        .alias
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ALIAS_PLACEHOLDER.to_owned());
+    let mut lnd_conf = generate::lnd_conf(
+        &config,
+        &RenderContext {
+            alias: &alias,
+            listen_ips: vec![Ipv4Addr::UNSPECIFIED.into()],
+            use_neutrino: bitcoind.is_none() || backend == SimulatedBackend::Neutrino,
+            rpc_polling: backend == SimulatedBackend::BitcoindPolling,
+            bitcoind_fees: false,
+            bitcoind_neutrino_peer: compact_filters,
+            bitcoind: bitcoind.as_ref(),
+        },
+    );
+
+    for warning in custom_conf::merge(&mut lnd_conf, &config)? {
+        eprintln!("warning: {}", warning);
+    }
+
+    println!("# ==> lnd.conf <==");
+    print!("{}", lnd_conf);
+    println!();
+    println!("# ==> .backupignore <==");
+    println!("{}", generate::backup_ignore(config.network));
+    Ok(())
+}
@@ -0,0 +1,281 @@
+//! Checks that the Tor endpoints lnd is configured for answer before lnd starts, since lnd only
+//! logs a failing SOCKS proxy or control port once it tries to use them.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::config::{TorConfig, TorControlAuth};
+use crate::net::HostPort;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn connect(address: &str) -> Result<TcpStream, anyhow::Error> {
+    let host_port = HostPort::parse(address).map_err(anyhow::Error::msg)?;
+    let port = host_port
+        .port
+        .ok_or_else(|| anyhow::anyhow!("{} has no port", address))?;
+    let mut last_err = anyhow::anyhow!("{} did not resolve to any address", host_port.host);
+    for addr in (host_port.host.as_str(), port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_err = anyhow::anyhow!("{}: {}", addr, e),
+        }
+    }
+    Err(last_err)
+}
+
+/// Performs a SOCKS5 greeting offering no authentication, which Tor accepts.
+fn check_socks(address: &str) -> Result<(), anyhow::Error> {
+    let mut stream = connect(address)?;
+    stream.write_all(&[5, 1, 0])?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply)?;
+    match reply {
+        [5, 0] => Ok(()),
+        [5, 0xff] => anyhow::bail!("the SOCKS proxy requires authentication lnd cannot provide"),
+        _ => anyhow::bail!("not a SOCKS5 proxy (replied {:02x?})", reply),
+    }
+}
+
+/// Reads one control port reply, which may span several `250-` lines, returning its lines
+/// without the status code.
+fn read_reply(reader: &mut impl BufRead) -> Result<Vec<String>, anyhow::Error> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("the control port closed the connection");
+        }
+        let line = line.trim_end();
+        if !line.starts_with("250") {
+            anyhow::bail!("the control port replied {:?}", line);
+        }
+        lines.push(line.get(4..).unwrap_or_default().to_owned());
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(lines);
+        }
+    }
+}
+
+/// Authenticates to the control port the way lnd will.
+fn check_control(address: &str, auth: &TorControlAuth) -> Result<(), anyhow::Error> {
+    let stream = connect(address)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    writer.write_all(b"PROTOCOLINFO 1\r\n")?;
+    let info = read_reply(&mut reader)?;
+    let auth_line = info
+        .iter()
+        .find_map(|line| line.strip_prefix("AUTH "))
+        .ok_or_else(|| anyhow::anyhow!("PROTOCOLINFO did not list auth methods"))?;
+    let methods = auth_line
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("METHODS="))
+        .unwrap_or_default();
+    let has_method = |name: &str| methods.split(',').any(|m| m == name);
+    match auth {
+        TorControlAuth::Password { password } => {
+            if !has_method("HASHEDPASSWORD") {
+                anyhow::bail!(
+                    "Tor does not accept password authentication (methods: {})",
+                    methods
+                );
+            }
+            let escaped = password.replace('\\', "\\\\").replace('"', "\\\"");
+            writer.write_all(format!("AUTHENTICATE \"{}\"\r\n", escaped).as_bytes())?;
+            read_reply(&mut reader).map_err(|e| anyhow::anyhow!("authentication failed: {}", e))?;
+        }
+        TorControlAuth::Cookie => {
+            if !has_method("COOKIE") && !has_method("SAFECOOKIE") {
+                anyhow::bail!(
+                    "Tor does not accept cookie authentication (methods: {})",
+                    methods
+                );
+            }
+            let path = auth_line
+                .split("COOKIEFILE=")
+                .nth(1)
+                .and_then(|rest| rest.strip_prefix('"')?.split('"').next())
+                .ok_or_else(|| anyhow::anyhow!("PROTOCOLINFO did not report a cookie file"))?;
+            std::fs::read(path)
+                .map_err(|e| anyhow::anyhow!("cannot read Tor's cookie file {}: {}", path, e))?;
+        }
+    }
+    writer.write_all(b"QUIT\r\n")?;
+    Ok(())
+}
+
+/// Checks the SOCKS proxy and, if configured, the control port.
+pub fn preflight(config: &TorConfig) -> Result<(), anyhow::Error> {
+    check_socks(&config.socks)
+        .map_err(|e| anyhow::anyhow!("Tor SOCKS proxy {} is not usable: {}", config.socks, e))?;
+    if let Some(control) = &config.control {
+        check_control(&control.address, &control.auth).map_err(|e| {
+            anyhow::anyhow!("Tor control port {} is not usable: {}", control.address, e)
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// A local stand-in for Tor's SOCKS port that answers the greeting with `reply`.
+    fn socks_proxy(reply: &'static [u8]) -> String {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(reply).unwrap();
+        });
+        addr.to_string()
+    }
+
+    /// A local stand-in for Tor's control port that lists `auth` in its PROTOCOLINFO reply and
+    /// accepts the password `secret`. Sends every line it receives to the returned channel.
+    fn control_port(auth: String) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let line = line.unwrap();
+                let reply = if line == "PROTOCOLINFO 1" {
+                    format!(
+                        "250-PROTOCOLINFO 1\r\n250-{}\r\n250-VERSION Tor=\"0.4.8.9\"\r\n250 OK\r\n",
+                        auth
+                    )
+                } else if line == "AUTHENTICATE \"secret\"" {
+                    "250 OK\r\n".to_owned()
+                } else if line.starts_with("AUTHENTICATE") {
+                    "515 Authentication failed: Password did not match\r\n".to_owned()
+                } else {
+                    "250 closing connection\r\n".to_owned()
+                };
+                // the test may not care what was received
+                let _ = tx.send(line);
+                if writer.write_all(reply.as_bytes()).is_err() {
+                    break;
+                }
+            }
+        });
+        (addr.to_string(), rx)
+    }
+
+    fn password(password: &str) -> TorControlAuth {
+        TorControlAuth::Password {
+            password: password.to_owned(),
+        }
+    }
+
+    #[test]
+    fn accepts_socks5_without_authentication() {
+        check_socks(&socks_proxy(&[5, 0])).unwrap();
+    }
+
+    #[test]
+    fn rejects_other_socks_replies() {
+        let err = check_socks(&socks_proxy(&[5, 0xff])).unwrap_err();
+        assert!(
+            err.to_string().contains("requires authentication"),
+            "{}",
+            err
+        );
+        let err = check_socks(&socks_proxy(&[4, 0])).unwrap_err();
+        assert!(err.to_string().starts_with("not a SOCKS5 proxy"), "{}", err);
+        let err = check_socks("127.0.0.1").unwrap_err();
+        assert_eq!(err.to_string(), "127.0.0.1 has no port");
+    }
+
+    #[test]
+    fn reads_multi_line_replies() {
+        let mut reply =
+            "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\nQUIT\r\n".as_bytes();
+        assert_eq!(
+            read_reply(&mut reply).unwrap(),
+            ["PROTOCOLINFO 1", "AUTH METHODS=NULL", "OK"]
+        );
+        let err = read_reply(&mut "515 Bad authentication\r\n".as_bytes()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the control port replied \"515 Bad authentication\""
+        );
+        let err = read_reply(&mut "250-PROTOCOLINFO 1\r\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "the control port closed the connection");
+    }
+
+    #[test]
+    fn authenticates_with_a_password() {
+        let (address, received) = control_port("AUTH METHODS=HASHEDPASSWORD".to_owned());
+        check_control(&address, &password("secret")).unwrap();
+        assert_eq!(
+            received.iter().collect::<Vec<_>>(),
+            ["PROTOCOLINFO 1", "AUTHENTICATE \"secret\"", "QUIT"]
+        );
+
+        let (address, received) = control_port("AUTH METHODS=HASHEDPASSWORD".to_owned());
+        let err = check_control(&address, &password("a \"quoted\\\" one")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("authentication failed:"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            received.iter().nth(1).unwrap(),
+            "AUTHENTICATE \"a \\\"quoted\\\\\\\" one\""
+        );
+
+        let (address, _) = control_port("AUTH METHODS=COOKIE,SAFECOOKIE".to_owned());
+        let err = check_control(&address, &password("secret")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tor does not accept password authentication (methods: COOKIE,SAFECOOKIE)"
+        );
+    }
+
+    #[test]
+    fn reads_the_cookie_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let cookie = dir.path().join("control_auth_cookie");
+        let auth = format!(
+            "AUTH METHODS=COOKIE,SAFECOOKIE COOKIEFILE=\"{}\"",
+            cookie.display()
+        );
+
+        let (address, _) = control_port(auth.clone());
+        let err = check_control(&address, &TorControlAuth::Cookie).unwrap_err();
+        assert!(
+            err.to_string().starts_with("cannot read Tor's cookie file"),
+            "{}",
+            err
+        );
+
+        std::fs::write(&cookie, [7; 32]).unwrap();
+        let (address, received) = control_port(auth);
+        check_control(&address, &TorControlAuth::Cookie).unwrap();
+        assert_eq!(
+            received.iter().collect::<Vec<_>>(),
+            ["PROTOCOLINFO 1", "QUIT"]
+        );
+
+        let (address, _) = control_port("AUTH METHODS=HASHEDPASSWORD".to_owned());
+        let err = check_control(&address, &TorControlAuth::Cookie).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Tor does not accept cookie authentication (methods: HASHEDPASSWORD)"
+        );
+    }
+}
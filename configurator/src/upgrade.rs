@@ -0,0 +1,451 @@
+//! Upgrades older `config.yaml` layouts to the one [`Config`](crate::config::Config) reads, so a
+//! package upgrade never fails startup on a missing or renamed field.
+//!
+//! Configs carry their schema version in a top level `version` field. Configs written before it
+//! existed count as version 0 and may have any layout StartOS ever wrote, so every step checks
+//! what is there before changing it and is harmless on a config that is already up to date.
+
+use serde_yaml::{Mapping, Value};
+
+type Step = fn(&mut Mapping) -> Result<(), anyhow::Error>;
+
+/// `STEPS[n]` turns a version `n` config into a version `n + 1` one.
+const STEPS: &[Step] = &[v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6];
+
+/// Schema version of the current [`Config`](crate::config::Config).
+pub const CURRENT_VERSION: u64 = STEPS.len() as u64;
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_owned())
+}
+
+fn mapping_mut<'a>(map: &'a mut Mapping, name: &str) -> Option<&'a mut Mapping> {
+    map.get_mut(&key(name)).and_then(Value::as_mapping_mut)
+}
+
+fn set_default(map: &mut Mapping, name: &str, value: impl Into<Value>) {
+    map.entry(key(name)).or_insert(value.into());
+}
+
+/// lnd 0.17.0.2: `bitcoind.type: internal-proxy` became `internal`, and the watchtower client
+/// moved from `watchtowers.add-watchtowers` to `watchtowers.wt-client`.
+fn v0_to_v1(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    if let Some(bitcoind) = mapping_mut(config, "bitcoind") {
+        if bitcoind.get(&key("type")) == Some(&key("internal-proxy")) {
+            bitcoind.insert(key("type"), key("internal"));
+        }
+    }
+    if let Some(watchtowers) = mapping_mut(config, "watchtowers") {
+        if let Some(towers) = watchtowers.remove(&key("add-watchtowers")) {
+            let towers = towers.as_sequence().cloned().unwrap_or_default();
+            let mut client = Mapping::new();
+            if towers.is_empty() {
+                client.insert(key("enabled"), key("disabled"));
+            } else {
+                client.insert(key("enabled"), key("enabled"));
+                client.insert(key("add-watchtowers"), Value::Sequence(towers));
+            }
+            watchtowers.insert(key("wt-client"), Value::Mapping(client));
+        }
+    }
+    Ok(())
+}
+
+/// lnd 0.17.3: added `advanced.max-pending-channels`.
+fn v1_to_v2(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    if let Some(advanced) = mapping_mut(config, "advanced") {
+        set_default(advanced, "max-pending-channels", 5);
+    }
+    Ok(())
+}
+
+/// lnd 0.18.0.1: added the zero-conf, scid-alias and taproot protocol flags and the sweeper
+/// settings.
+fn v2_to_v3(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    let advanced = match mapping_mut(config, "advanced") {
+        Some(advanced) => advanced,
+        None => return Ok(()),
+    };
+    set_default(advanced, "protocol-zero-conf", false);
+    set_default(advanced, "protocol-option-scid-alias", false);
+    set_default(advanced, "protocol-simple-taproot-chans", false);
+    let sweeper = advanced
+        .entry(key("sweeper"))
+        .or_insert(Value::Mapping(Mapping::new()))
+        .as_mapping_mut()
+        .ok_or_else(|| anyhow::anyhow!("advanced.sweeper is not a mapping"))?;
+    set_default(sweeper, "sweeper-maxfeerate", 1000);
+    set_default(sweeper, "sweeper-nodeadlineconftarget", 1008);
+    for ratio in [
+        "sweeper-budget-tolocalratio",
+        "sweeper-budget-anchorcpfpratio",
+        "sweeper-budget-deadlinehtlcratio",
+        "sweeper-budget-nodeadlinehtlcratio",
+    ] {
+        set_default(sweeper, ratio, 0.5);
+    }
+    Ok(())
+}
+
+/// Replaced the free-form `advanced.debug-level` with the structured `advanced.logging`. Its
+/// `<level>,<subsystem>=<level>,...` value is split into `level` and the `subsystems` list.
+fn v3_to_v4(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    let advanced = match mapping_mut(config, "advanced") {
+        Some(advanced) => advanced,
+        None => return Ok(()),
+    };
+    let debug_level = match advanced.remove(&key("debug-level")) {
+        Some(Value::String(debug_level)) => debug_level,
+        Some(other) => anyhow::bail!("advanced.debug-level must be a string, got {:?}", other),
+        None => return Ok(()),
+    };
+    let mut logging = Mapping::new();
+    let mut subsystems = Vec::new();
+    for part in debug_level
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        match part.split_once('=') {
+            Some((subsystem, level)) => {
+                let mut entry = Mapping::new();
+                entry.insert(key("subsystem"), key(subsystem.trim()));
+                entry.insert(key("level"), key(level.trim()));
+                subsystems.push(Value::Mapping(entry));
+            }
+            None => {
+                logging.insert(key("level"), key(part));
+            }
+        }
+    }
+    if !subsystems.is_empty() {
+        logging.insert(key("subsystems"), Value::Sequence(subsystems));
+    }
+    advanced
+        .entry(key("logging"))
+        .or_insert(Value::Mapping(logging));
+    Ok(())
+}
+
+/// `externalip` became a list to advertise several clearnet addresses in hybrid mode.
+fn v4_to_v5(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    let addresses = match config.remove(&key("externalip")) {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::String(address)) if address.trim().is_empty() => Vec::new(),
+        Some(Value::String(address)) => vec![Value::String(address)],
+        Some(Value::Sequence(addresses)) => addresses,
+        Some(other) => anyhow::bail!("externalip must be a string, got {:?}", other),
+    };
+    config.insert(key("externalip"), Value::Sequence(addresses));
+    Ok(())
+}
+
+/// The keys presets set are left unset by the config form until the user picks a value, see
+/// [`crate::presets`]: numbers are empty, and booleans (which StartOS cannot leave empty) became
+/// `default`, `enabled` or `disabled`. Earlier forms wrote every key, so values equal to the
+/// defaults count as unset.
+fn v5_to_v6(config: &mut Mapping) -> Result<(), anyhow::Error> {
+    for (path, default) in crate::presets::defaults() {
+        let (parents, name) = path.rsplit_once('.').unwrap_or(("", path));
+        let parent = parents
+            .split('.')
+            .filter(|parent| !parent.is_empty())
+            .try_fold(&mut *config, |map, parent| mapping_mut(map, parent));
+        let value = match parent.and_then(|parent| parent.get_mut(&key(name))) {
+            Some(value) => value,
+            None => continue,
+        };
+        *value = match value {
+            Value::Bool(_) if *value == default => key(crate::presets::UNSET),
+            Value::Bool(true) => key("enabled"),
+            Value::Bool(false) => key("disabled"),
+            _ if *value == default => Value::Null,
+            _ => continue,
+        };
+    }
+    Ok(())
+}
+
+/// Brings `config` up to [`CURRENT_VERSION`] in place and returns the version it had before.
+pub fn upgrade(config: &mut Value) -> Result<u64, anyhow::Error> {
+    let config = config
+        .as_mapping_mut()
+        .ok_or_else(|| anyhow::anyhow!("config.yaml is not a mapping"))?;
+    let version = match config.get(&key("version")) {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            anyhow::anyhow!("config.yaml version must be an integer, got {:?}", version)
+        })?,
+    };
+    if version > CURRENT_VERSION {
+        anyhow::bail!(
+            "config.yaml has version {}, but this configurator only reads up to version {}. Was LND downgraded?",
+            version,
+            CURRENT_VERSION
+        );
+    }
+    for (from, step) in STEPS.iter().enumerate().skip(version as usize) {
+        step(config).map_err(|e| {
+            anyhow::anyhow!("failed to upgrade config.yaml from version {}: {}", from, e)
+        })?;
+    }
+    config.insert(key("version"), Value::from(CURRENT_VERSION));
+    Ok(version)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{Config, WtClient};
+
+    /// A config as written by the 0.17.0 package, before any of the steps.
+    const LEGACY_CONFIG: &str = r#"
+control-tor-address: 6sfczgaiqlnaa6nqtckq5n4w2da7tatxzzcrwiwjrmvqdnnga6zpxjqd.onion
+peer-tor-address: f76b2bryp34lw6rugevwy3b7nekva2cfbdb447xdrcjxlimnn6qcf2ad.onion
+watchtower-tor-address: u4q5xsgrnas2mssrurcm6vttosbquv5guogpojcdv54mbpocfbizdsqd.onion
+alias: ~
+externalip: ~
+color: ff9900
+accept-keysend: true
+accept-amp: false
+reject-htlc: false
+min-chan-size: ~
+max-chan-size: ~
+tor:
+  use-tor-only: false
+  stream-isolation: false
+bitcoind:
+  type: internal-proxy
+  user: bitcoin
+  password: secret
+autopilot:
+  enabled: false
+  private: false
+  maxchannels: 5
+  allocation: 60
+  min-channel-size: 20000
+  max-channel-size: 16777215
+  advanced:
+    min-confirmations: 1
+    confirmation-target: 1
+watchtowers:
+  wt-server: true
+  add-watchtowers:
+    - 02aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa@tower.example.com:9911
+advanced:
+  debug-level: info
+  db-bolt-no-freelist-sync: true
+  db-bolt-auto-compact: true
+  db-bolt-auto-compact-min-age: 168
+  db-bolt-db-timeout: 60
+  recovery-window: ~
+  payments-expiration-grace-period: 30
+  default-remote-max-htlcs: 483
+  max-channel-fee-allocation: 0.5
+  max-commit-fee-rate-anchors: 100
+  protocol-wumbo-channels: false
+  protocol-no-anchors: false
+  protocol-disable-script-enforced-lease: false
+  gc-canceled-invoices-on-startup: false
+  allow-circular-route: false
+  bitcoin:
+    default-channel-confirmations: 3
+    min-htlc: 1
+    min-htlc-out: 1000
+    base-fee: 1000
+    fee-rate: 1
+    time-lock-delta: 40
+"#;
+
+    fn parse(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    /// [`LEGACY_CONFIG`] upgraded to the current layout, a valid config for other tests to vary.
+    pub fn legacy_config() -> Config {
+        Config::from_value(parse(LEGACY_CONFIG)).unwrap()
+    }
+
+    fn apply(step: Step, yaml: &str) -> Value {
+        let mut value = parse(yaml);
+        step(value.as_mapping_mut().unwrap()).unwrap();
+        value
+    }
+
+    #[test]
+    fn legacy_config_upgrades_to_current() {
+        let mut value = parse(LEGACY_CONFIG);
+        assert_eq!(upgrade(&mut value).unwrap(), 0);
+        assert_eq!(value["version"].as_u64(), Some(CURRENT_VERSION));
+        crate::presets::apply(&mut value).unwrap();
+        let config: Config = serde_yaml::from_value(value).unwrap();
+        crate::validate::validate(&config).unwrap();
+        assert_eq!(config.advanced.max_pending_channels, 5);
+        assert_eq!(config.advanced.logging.debug_level(), "info");
+        assert!(matches!(
+            config.watchtowers.wt_client,
+            WtClient::Enabled { .. }
+        ));
+    }
+
+    #[test]
+    fn current_config_is_left_alone() {
+        let mut value = parse(LEGACY_CONFIG);
+        upgrade(&mut value).unwrap();
+        let upgraded = value.clone();
+        assert_eq!(upgrade(&mut value).unwrap(), CURRENT_VERSION);
+        assert_eq!(value, upgraded);
+    }
+
+    /// The config form stamps the version it saves, so it has to follow new steps.
+    #[test]
+    fn config_form_saves_the_current_version() {
+        let set_config = include_str!("../../scripts/services/setConfig.ts");
+        let version = set_config
+            .lines()
+            .find_map(|line| line.strip_prefix("const CONFIG_VERSION = "))
+            .and_then(|rest| rest.strip_suffix(';'))
+            .expect("CONFIG_VERSION in scripts/services/setConfig.ts");
+        assert_eq!(
+            version.parse::<u64>().unwrap(),
+            CURRENT_VERSION,
+            "bump CONFIG_VERSION in scripts/services/setConfig.ts"
+        );
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut value = parse(&format!("version: {}", CURRENT_VERSION + 1));
+        assert!(upgrade(&mut value).is_err());
+        let mut value = parse("version: one");
+        assert!(upgrade(&mut value).is_err());
+    }
+
+    #[test]
+    fn v0_to_v1_renames_internal_proxy() {
+        let value = apply(
+            v0_to_v1,
+            "bitcoind: {type: internal-proxy, user: a, password: b}",
+        );
+        assert_eq!(value["bitcoind"]["type"], key("internal"));
+        let value = apply(v0_to_v1, "bitcoind: {type: none}");
+        assert_eq!(value["bitcoind"]["type"], key("none"));
+    }
+
+    #[test]
+    fn v0_to_v1_moves_watchtowers_to_client() {
+        let value = apply(
+            v0_to_v1,
+            "watchtowers: {wt-server: false, add-watchtowers: [a@b:9911]}",
+        );
+        assert_eq!(
+            value["watchtowers"],
+            parse("{wt-server: false, wt-client: {enabled: enabled, add-watchtowers: [a@b:9911]}}")
+        );
+        let value = apply(
+            v0_to_v1,
+            "watchtowers: {wt-server: false, add-watchtowers: []}",
+        );
+        assert_eq!(
+            value["watchtowers"],
+            parse("{wt-server: false, wt-client: {enabled: disabled}}")
+        );
+        let current = "watchtowers: {wt-server: true, wt-client: {enabled: disabled}}";
+        assert_eq!(apply(v0_to_v1, current), parse(current));
+    }
+
+    #[test]
+    fn v1_to_v2_adds_max_pending_channels() {
+        let value = apply(v1_to_v2, "advanced: {}");
+        assert_eq!(value["advanced"]["max-pending-channels"].as_u64(), Some(5));
+        let value = apply(v1_to_v2, "advanced: {max-pending-channels: 2}");
+        assert_eq!(value["advanced"]["max-pending-channels"].as_u64(), Some(2));
+    }
+
+    #[test]
+    fn v2_to_v3_adds_protocol_flags_and_sweeper() {
+        let value = apply(v2_to_v3, "advanced: {}");
+        assert_eq!(value["advanced"]["protocol-zero-conf"], Value::Bool(false));
+        assert_eq!(
+            value["advanced"]["sweeper"]["sweeper-maxfeerate"].as_u64(),
+            Some(1000)
+        );
+        let value = apply(
+            v2_to_v3,
+            "advanced: {protocol-zero-conf: true, sweeper: {sweeper-maxfeerate: 50}}",
+        );
+        assert_eq!(value["advanced"]["protocol-zero-conf"], Value::Bool(true));
+        assert_eq!(
+            value["advanced"]["sweeper"]["sweeper-maxfeerate"].as_u64(),
+            Some(50)
+        );
+        assert_eq!(
+            value["advanced"]["sweeper"]["sweeper-budget-tolocalratio"].as_f64(),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn v3_to_v4_splits_debug_level() {
+        let value = apply(v3_to_v4, "advanced: {debug-level: info}");
+        assert_eq!(value["advanced"], parse("{logging: {level: info}}"));
+        let value = apply(
+            v3_to_v4,
+            "advanced: {debug-level: 'debug,HSWC=trace, PEER=info'}",
+        );
+        assert_eq!(
+            value["advanced"],
+            parse(
+                "{logging: {level: debug, subsystems: [{subsystem: HSWC, level: trace}, {subsystem: PEER, level: info}]}}"
+            )
+        );
+        let current = "advanced: {logging: {level: warn}}";
+        assert_eq!(apply(v3_to_v4, current), parse(current));
+    }
+
+    #[test]
+    fn v4_to_v5_makes_externalip_a_list() {
+        let value = apply(v4_to_v5, "externalip: ~");
+        assert_eq!(value["externalip"], parse("[]"));
+        let value = apply(v4_to_v5, "externalip: ''");
+        assert_eq!(value["externalip"], parse("[]"));
+        let value = apply(v4_to_v5, "externalip: 203.0.113.7:9736");
+        assert_eq!(value["externalip"], parse("['203.0.113.7:9736']"));
+        let current = "externalip: [203.0.113.7, node.example.com]";
+        assert_eq!(apply(v4_to_v5, current), parse(current));
+    }
+
+    #[test]
+    fn v5_to_v6_unsets_values_equal_to_the_defaults() {
+        let value = apply(
+            v5_to_v6,
+            "
+accept-keysend: true
+accept-amp: true
+autopilot: {enabled: false}
+advanced:
+  max-pending-channels: 5
+  protocol-zero-conf: false
+  allow-circular-route: true
+  bitcoin: {fee-rate: 1, time-lock-delta: 80}
+",
+        );
+        assert_eq!(
+            value,
+            parse(
+                "
+accept-keysend: default
+accept-amp: enabled
+autopilot: {enabled: false}
+advanced:
+  max-pending-channels: ~
+  protocol-zero-conf: default
+  allow-circular-route: enabled
+  bitcoin: {fee-rate: ~, time-lock-delta: 80}
+"
+            )
+        );
+        let current = "{accept-keysend: disabled, advanced: {max-pending-channels: ~}}";
+        assert_eq!(apply(v5_to_v6, current), parse(current));
+    }
+}
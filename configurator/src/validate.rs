@@ -0,0 +1,776 @@
+use std::fmt::{self, Display};
+use std::net::IpAddr;
+
+use crate::config::{
+    BitcoinCoreConfig, BitcoindAuth, Config, DbConfig, Estimator, FeeSource, Network, OnionConfig,
+    PortsConfig, SubsystemLevel, WtClient,
+};
+use crate::custom_conf;
+use crate::net::{check_onion_v3, http_url_host_port, HostPort, WatchtowerUri};
+
+/// Largest channel lnd accepts without `protocol.wumbo-channels` (BOLT-0002).
+const MAX_NON_WUMBO_CHAN_SIZE: u64 = 16_777_215;
+/// lnd rejects node aliases longer than this many bytes.
+const MAX_ALIAS_LEN: usize = 32;
+/// Log subsystems of lnd and the libraries it logs for, as listed by `lncli debuglevel --show`.
+const LND_SUBSYSTEMS: &[&str] = &[
+    "ARPC", "ATPL", "BRAR", "BTCN", "BTWL", "CHAC", "CHBU", "CHCL", "CHDB", "CHFD", "CHFT", "CHNF",
+    "CLUS", "CMGR", "CNCT", "CRTR", "DISC", "DRPC", "FNDG", "GRPH", "HLCK", "HSWC", "INVC", "IRPC",
+    "KCHN", "LNWL", "LTND", "NANN", "NRPC", "NTFN", "NTFR", "PEER", "PRNF", "PROM", "RPCP", "RPCS",
+    "RRPC", "SGNR", "SPHX", "SRVR", "SWPR", "TORC", "UTXN", "WLKT", "WTCL", "WTWR",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Key path into `config.yaml`, e.g. `autopilot.allocation`.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every problem found in a config, reported together.
+#[derive(Debug, Clone, Default)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl ValidationErrors {
+    pub fn push(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    pub fn check(&mut self, ok: bool, path: &str, message: impl FnOnce() -> String) {
+        if !ok {
+            self.push(path, message());
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid config.yaml ({} problem(s)):", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+fn check_ratio(errors: &mut ValidationErrors, path: &str, value: f64) {
+    errors.check((0.0..=1.0).contains(&value), path, || {
+        format!("must be between 0 and 1, got {}", value)
+    });
+}
+
+/// Semantic checks that serde cannot express. Runs before anything is written so a bad config
+/// never reaches lnd.
+pub fn validate(config: &Config) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+
+    for (key, address) in [
+        ("control-tor-address", &config.control_tor_address),
+        ("peer-tor-address", &config.peer_tor_address),
+        ("watchtower-tor-address", &config.watchtower_tor_address),
+    ] {
+        if let Err(e) = check_onion_v3(address) {
+            errors.push(key, e);
+        }
+    }
+    for (idx, address) in config.externalip.iter().enumerate() {
+        match HostPort::parse(address) {
+            Ok(address) if config.nat && address.host.parse::<IpAddr>().is_err() => errors.push(
+                format!("externalip[{}]", idx),
+                format!(
+                    "lnd cannot advertise the DNS name {} together with nat; use an IP address or disable nat",
+                    address.host
+                ),
+            ),
+            Ok(_) => (),
+            Err(e) => errors.push(format!("externalip[{}]", idx), e),
+        }
+    }
+
+    let neutrino = &config.neutrino;
+    errors.check(
+        config.network != Network::Regtest
+            || config.bitcoind != BitcoinCoreConfig::None
+            || !neutrino.connect.is_empty()
+            || !neutrino.add_peers.is_empty(),
+        "bitcoind",
+        || {
+            "regtest has no public neutrino peers; select a bitcoind backend or set neutrino.connect"
+                .to_owned()
+        },
+    );
+    for (key, peers) in [
+        ("neutrino.connect", &neutrino.connect),
+        ("neutrino.add-peers", &neutrino.add_peers),
+    ] {
+        for (idx, peer) in peers.iter().enumerate() {
+            if let Err(e) = HostPort::parse(peer) {
+                errors.push(format!("{}[{}]", key, idx), e);
+            }
+        }
+    }
+    match &neutrino.fee_source {
+        FeeSource::Url { url } => {
+            if let Err(e) = http_url_host_port(url) {
+                errors.push("neutrino.fee-source.url", e);
+            }
+        }
+        FeeSource::Bitcoind => errors.check(
+            config.bitcoind != BitcoinCoreConfig::None,
+            "neutrino.fee-source",
+            || "bitcoind fee estimates require a bitcoind backend".to_owned(),
+        ),
+        FeeSource::Default => (),
+    }
+
+    if let BitcoinCoreConfig::External { host, auth, .. } = &config.bitcoind {
+        errors.check(!host.trim().is_empty(), "bitcoind.host", || {
+            "must not be empty".to_owned()
+        });
+        if let BitcoindAuth::Cookie { path } = auth {
+            errors.check(path.is_absolute(), "bitcoind.auth.path", || {
+                format!("must be an absolute path, got {}", path.display())
+            });
+        }
+    }
+
+    if let DbConfig::Postgres {
+        dsn,
+        max_connections,
+    } = &config.db
+    {
+        errors.check(
+            dsn.starts_with("postgres://") || dsn.starts_with("postgresql://"),
+            "db.dsn",
+            || "must be a postgres:// or postgresql:// URL".to_owned(),
+        );
+        errors.check(max_connections != &Some(0), "db.max-connections", || {
+            "must be at least 1".to_owned()
+        });
+    }
+
+    for (idx, line) in config.custom_lnd_conf.iter().enumerate() {
+        if let Err(e) = custom_conf::parse_line(line) {
+            errors.push(format!("custom-lnd-conf[{}]", idx), e);
+        }
+    }
+
+    if let Some(alias) = &config.alias {
+        errors.check(
+            !alias.is_empty() && alias.len() <= MAX_ALIAS_LEN,
+            "alias",
+            || {
+                format!(
+                    "must be between 1 and {} bytes, got {}",
+                    MAX_ALIAS_LEN,
+                    alias.len()
+                )
+            },
+        );
+    }
+    errors.check(
+        config.color.len() == 6 && config.color.chars().all(|c| c.is_ascii_hexdigit()),
+        "color",
+        || format!("must be a 6 digit hex RGB value, got {:?}", config.color),
+    );
+
+    if let (Some(min), Some(max)) = (config.min_chan_size, config.max_chan_size) {
+        errors.check(min <= max, "min-chan-size", || {
+            format!("{} exceeds max-chan-size ({})", min, max)
+        });
+    }
+    if let Some(max) = config.max_chan_size {
+        errors.check(
+            config.advanced.protocol_wumbo_channels || max <= MAX_NON_WUMBO_CHAN_SIZE,
+            "max-chan-size",
+            || {
+                format!(
+                    "{} exceeds {} sats; enable advanced.protocol-wumbo-channels for larger channels",
+                    max, MAX_NON_WUMBO_CHAN_SIZE
+                )
+            },
+        );
+    }
+
+    // dependencies with a safe resolution are fixed by AdvancedConfig::fix_protocol_dependencies
+    errors.check(
+        !(config.advanced.protocol_simple_taproot_chans && config.advanced.protocol_no_anchors),
+        "advanced.protocol-simple-taproot-chans",
+        || {
+            "taproot channels require anchors; disable advanced.protocol-no-anchors or advanced.protocol-simple-taproot-chans".to_owned()
+        },
+    );
+
+    let logging = &config.advanced.logging;
+    for SubsystemLevel { subsystem, .. } in &logging.subsystems {
+        errors.check(
+            LND_SUBSYSTEMS.contains(&subsystem.as_str()),
+            &format!("advanced.logging.subsystems.{}", subsystem),
+            || {
+                format!(
+                    "unknown lnd subsystem, expected one of {}",
+                    LND_SUBSYSTEMS.join(", ")
+                )
+            },
+        );
+    }
+    errors.check(
+        logging.max_log_file_size != Some(0),
+        "advanced.logging.max-log-file-size",
+        || "must be at least 1 MB".to_owned(),
+    );
+
+    errors.check(
+        !config.tor.stream_isolation || config.tor.use_tor_only,
+        "tor.stream-isolation",
+        || "requires tor.use-tor-only to be enabled".to_owned(),
+    );
+    match HostPort::parse(&config.tor.socks) {
+        Ok(socks) => errors.check(socks.port.is_some(), "tor.socks", || {
+            "must include a port".to_owned()
+        }),
+        Err(e) => errors.push("tor.socks", e),
+    }
+    if let Some(control) = &config.tor.control {
+        match HostPort::parse(&control.address) {
+            Ok(address) => errors.check(address.port.is_some(), "tor.control.address", || {
+                "must include a port".to_owned()
+            }),
+            Err(e) => errors.push("tor.control.address", e),
+        }
+    }
+    if let OnionConfig::AutoCreate {
+        private_key_path, ..
+    } = &config.tor.onion
+    {
+        errors.check(config.tor.control.is_some(), "tor.onion", || {
+            "lnd can only create its own onion through tor.control".to_owned()
+        });
+        if let Some(path) = private_key_path {
+            errors.check(path.is_absolute(), "tor.onion.private-key-path", || {
+                format!("must be an absolute path, got {}", path.display())
+            });
+        }
+    }
+    // hybrid mode: reachable over clearnet and Tor
+    if config.tor.use_tor_only {
+        errors.check(config.externalip.is_empty(), "externalip", || {
+            format!(
+                "advertising {} would reveal the node's clearnet address while tor.use-tor-only hides it; disable tor.use-tor-only for hybrid mode or remove externalip",
+                config.externalip.join(", ")
+            )
+        });
+        errors.check(!config.nat, "nat", || {
+            "would advertise the node's clearnet address while tor.use-tor-only hides it; disable tor.use-tor-only for hybrid mode".to_owned()
+        });
+    }
+
+    let autopilot = &config.autopilot;
+    errors.check(
+        (0.0..=100.0).contains(&autopilot.allocation),
+        "autopilot.allocation",
+        || format!("must be between 0 and 100, got {}", autopilot.allocation),
+    );
+    errors.check(
+        autopilot.min_channel_size <= autopilot.max_channel_size,
+        "autopilot.min-channel-size",
+        || {
+            format!(
+                "{} exceeds autopilot.max-channel-size ({})",
+                autopilot.min_channel_size, autopilot.max_channel_size
+            )
+        },
+    );
+
+    let advanced = &config.advanced;
+    errors.check(
+        advanced.max_channel_fee_allocation > 0.0 && advanced.max_channel_fee_allocation <= 1.0,
+        "advanced.max-channel-fee-allocation",
+        || {
+            format!(
+                "must be greater than 0 and at most 1, got {}",
+                advanced.max_channel_fee_allocation
+            )
+        },
+    );
+
+    let sweeper = &advanced.sweeper;
+    check_ratio(
+        &mut errors,
+        "advanced.sweeper.sweeper-budget-tolocalratio",
+        sweeper.sweeper_budget_tolocalratio,
+    );
+    check_ratio(
+        &mut errors,
+        "advanced.sweeper.sweeper-budget-anchorcpfpratio",
+        sweeper.sweeper_budget_anchorcpfpratio,
+    );
+    check_ratio(
+        &mut errors,
+        "advanced.sweeper.sweeper-budget-deadlinehtlcratio",
+        sweeper.sweeper_budget_deadlinehtlcratio,
+    );
+    check_ratio(
+        &mut errors,
+        "advanced.sweeper.sweeper-budget-nodeadlinehtlcratio",
+        sweeper.sweeper_budget_nodeadlinehtlcratio,
+    );
+
+    if let WtClient::Enabled { add_watchtowers } = &config.watchtowers.wt_client {
+        for (idx, uri) in add_watchtowers.iter().enumerate() {
+            if let Err(e) = WatchtowerUri::parse(uri) {
+                errors.push(format!("watchtowers.wt-client.add-watchtowers[{}]", idx), e);
+            }
+        }
+    }
+
+    let defaults = PortsConfig::default().named();
+    for (&(key, port), &(_, default)) in config.ports.named().iter().zip(&defaults) {
+        errors.check(port == default, key, || {
+            format!(
+                "must be {}: the StartOS interfaces in manifest.yaml and the services depending on lnd only reach it there, got {}",
+                default, port
+            )
+        });
+    }
+
+    let gossip = &config.gossip;
+    errors.check(
+        gossip.max_channel_update_burst > 0,
+        "gossip.max-channel-update-burst",
+        || "must be greater than 0".to_owned(),
+    );
+    errors.check(
+        gossip.channel_update_interval > 0,
+        "gossip.channel-update-interval",
+        || "must be greater than 0".to_owned(),
+    );
+    errors.check(gossip.msg_rate_bytes > 0, "gossip.msg-rate-bytes", || {
+        "must be greater than 0".to_owned()
+    });
+    errors.check(
+        gossip.msg_burst_bytes >= gossip.msg_rate_bytes,
+        "gossip.msg-burst-bytes",
+        || {
+            format!(
+                "must be at least gossip.msg-rate-bytes ({}), got {}",
+                gossip.msg_rate_bytes, gossip.msg_burst_bytes
+            )
+        },
+    );
+
+    let routing = &config.routing;
+    check_ratio(
+        &mut errors,
+        "routing.min-route-probability",
+        routing.min_route_probability,
+    );
+    match &routing.estimator {
+        Estimator::Apriori(apriori) => {
+            check_ratio(
+                &mut errors,
+                "routing.estimator.hop-probability",
+                apriori.hop_probability,
+            );
+            check_ratio(&mut errors, "routing.estimator.weight", apriori.weight);
+            errors.check(
+                apriori.penalty_half_life > 0,
+                "routing.estimator.penalty-half-life",
+                || "must be greater than 0".to_owned(),
+            );
+            // lnd refuses anything below 0.75
+            errors.check(
+                (0.75..=1.0).contains(&apriori.capacity_fraction),
+                "routing.estimator.capacity-fraction",
+                || {
+                    format!(
+                        "must be between 0.75 and 1, got {}",
+                        apriori.capacity_fraction
+                    )
+                },
+            );
+        }
+        Estimator::Bimodal(bimodal) => {
+            errors.check(bimodal.scale > 0, "routing.estimator.scale", || {
+                "must be greater than 0".to_owned()
+            });
+            check_ratio(
+                &mut errors,
+                "routing.estimator.node-weight",
+                bimodal.node_weight,
+            );
+            errors.check(
+                bimodal.decay_time > 0,
+                "routing.estimator.decay-time",
+                || "must be greater than 0".to_owned(),
+            );
+        }
+    }
+
+    errors.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BimodalEstimator, DbConfig, LogLevel, TorControlAuth, TorControlConfig};
+    use crate::upgrade::tests::legacy_config;
+
+    /// The key paths of every problem `validate` finds.
+    fn error_paths(config: &Config) -> Vec<String> {
+        match validate(config) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.0.into_iter().map(|e| e.path).collect(),
+        }
+    }
+
+    fn error_messages(config: &Config) -> Vec<String> {
+        match validate(config) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.0.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_legacy_config() {
+        assert_eq!(error_paths(&legacy_config()), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_every_problem_together() {
+        let mut config = legacy_config();
+        config.color = "orange".to_owned();
+        config.alias = Some(String::new());
+        config.autopilot.allocation = 150.0;
+        let errors = validate(&config).unwrap_err();
+        assert_eq!(
+            errors.0.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+            ["alias", "color", "autopilot.allocation"]
+        );
+        let message = errors.to_string();
+        assert!(
+            message.starts_with("invalid config.yaml (3 problem(s)):\n  - alias: "),
+            "{}",
+            message
+        );
+        assert!(message.contains("\n  - color: must be a 6 digit hex RGB value"));
+    }
+
+    #[test]
+    fn rejects_invalid_onions() {
+        let mut config = legacy_config();
+        config.control_tor_address = "example.com".to_owned();
+        config.watchtower_tor_address = String::new();
+        assert_eq!(
+            error_paths(&config),
+            ["control-tor-address", "watchtower-tor-address"]
+        );
+    }
+
+    #[test]
+    fn checks_externalip_entries() {
+        let mut config = legacy_config();
+        config.externalip = vec![
+            "203.0.113.7".to_owned(),
+            "[2001:db8::1]:9736".to_owned(),
+            "node.example.com".to_owned(),
+            "203.0.113.7:0".to_owned(),
+        ];
+        assert_eq!(error_paths(&config), ["externalip[3]"]);
+
+        config.externalip.pop();
+        config.nat = true;
+        assert_eq!(error_paths(&config), ["externalip[2]"]);
+    }
+
+    #[test]
+    fn tor_only_hides_clearnet_addresses() {
+        let mut config = legacy_config();
+        config.tor.use_tor_only = true;
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.externalip = vec!["203.0.113.7".to_owned()];
+        config.nat = true;
+        assert_eq!(error_paths(&config), ["externalip", "nat"]);
+    }
+
+    #[test]
+    fn regtest_needs_a_backend_or_neutrino_peers() {
+        let mut config = legacy_config();
+        config.network = Network::Regtest;
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.bitcoind = BitcoinCoreConfig::None;
+        assert_eq!(error_paths(&config), ["bitcoind"]);
+        config.neutrino.connect = vec!["10.0.0.2:18444".to_owned()];
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.neutrino.add_peers = vec!["bad host".to_owned()];
+        assert_eq!(error_paths(&config), ["neutrino.add-peers[0]"]);
+    }
+
+    #[test]
+    fn checks_neutrino_fee_source() {
+        let mut config = legacy_config();
+        config.neutrino.fee_source = FeeSource::Url {
+            url: "ftp://fees.example.com".to_owned(),
+        };
+        assert_eq!(error_paths(&config), ["neutrino.fee-source.url"]);
+        config.neutrino.fee_source = FeeSource::Bitcoind;
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.bitcoind = BitcoinCoreConfig::None;
+        assert_eq!(error_paths(&config), ["neutrino.fee-source"]);
+    }
+
+    #[test]
+    fn checks_external_bitcoind() {
+        let mut config = legacy_config();
+        config.bitcoind = BitcoinCoreConfig::External {
+            host: " ".to_owned(),
+            rpc_port: None,
+            zmq_host: None,
+            zmq_block_port: 28332,
+            zmq_tx_port: 28333,
+            p2p_port: None,
+            auth: BitcoindAuth::Cookie {
+                path: "bitcoin/.cookie".into(),
+            },
+        };
+        assert_eq!(
+            error_paths(&config),
+            ["bitcoind.host", "bitcoind.auth.path"]
+        );
+    }
+
+    #[test]
+    fn checks_postgres() {
+        let mut config = legacy_config();
+        config.db = DbConfig::Postgres {
+            dsn: "mysql://lnd@db/lnd".to_owned(),
+            max_connections: Some(0),
+        };
+        assert_eq!(error_paths(&config), ["db.dsn", "db.max-connections"]);
+        config.db = DbConfig::Postgres {
+            dsn: "postgresql://lnd:pw@postgres.embassy:5432/lnd".to_owned(),
+            max_connections: None,
+        };
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn checks_custom_lnd_conf_lines() {
+        let mut config = legacy_config();
+        config.custom_lnd_conf = vec![
+            "bitcoin.defaultremotedelay=144".to_owned(),
+            "no value".to_owned(),
+            "noseedbackup=true".to_owned(),
+        ];
+        assert_eq!(
+            error_paths(&config),
+            ["custom-lnd-conf[1]", "custom-lnd-conf[2]"]
+        );
+    }
+
+    #[test]
+    fn checks_alias_length() {
+        let mut config = legacy_config();
+        config.alias = Some("x".repeat(MAX_ALIAS_LEN));
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.alias = Some("x".repeat(MAX_ALIAS_LEN + 1));
+        assert_eq!(error_paths(&config), ["alias"]);
+    }
+
+    #[test]
+    fn checks_channel_sizes() {
+        let mut config = legacy_config();
+        config.min_chan_size = Some(2_000_000);
+        config.max_chan_size = Some(1_000_000);
+        assert_eq!(error_paths(&config), ["min-chan-size"]);
+
+        config.min_chan_size = None;
+        config.max_chan_size = Some(MAX_NON_WUMBO_CHAN_SIZE + 1);
+        assert_eq!(error_paths(&config), ["max-chan-size"]);
+        config.advanced.protocol_wumbo_channels = true;
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_taproot_without_anchors() {
+        let mut config = legacy_config();
+        config.advanced.protocol_simple_taproot_chans = true;
+        assert_eq!(error_paths(&config), Vec::<String>::new());
+        config.advanced.protocol_no_anchors = true;
+        assert_eq!(
+            error_paths(&config),
+            ["advanced.protocol-simple-taproot-chans"]
+        );
+    }
+
+    #[test]
+    fn checks_logging() {
+        let mut config = legacy_config();
+        let logging = &mut config.advanced.logging;
+        for subsystem in ["HSWC", "NOPE"] {
+            logging.subsystems.push(SubsystemLevel {
+                subsystem: subsystem.to_owned(),
+                level: LogLevel::Debug,
+            });
+        }
+        logging.max_log_file_size = Some(0);
+        assert_eq!(
+            error_paths(&config),
+            [
+                "advanced.logging.subsystems.NOPE",
+                "advanced.logging.max-log-file-size"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_watchtower_uris() {
+        let pubkey = format!("02{}", "ab".repeat(32));
+        let mut config = legacy_config();
+        config.watchtowers.wt_client = WtClient::Enabled {
+            add_watchtowers: vec![
+                format!("{}@tower.example.com:9911", pubkey),
+                format!("{}@tower.example.com", &pubkey[..64]),
+                format!("{}@tower.example.com", pubkey.replace('a', "g")),
+                "tower.example.com:9911".to_owned(),
+                format!("{}@bad host", pubkey),
+            ],
+        };
+        assert_eq!(
+            error_paths(&config),
+            [
+                "watchtowers.wt-client.add-watchtowers[1]",
+                "watchtowers.wt-client.add-watchtowers[2]",
+                "watchtowers.wt-client.add-watchtowers[3]",
+                "watchtowers.wt-client.add-watchtowers[4]"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_tor() {
+        let mut config = legacy_config();
+        config.tor.stream_isolation = true;
+        config.tor.socks = "embassy".to_owned();
+        config.tor.onion = OnionConfig::AutoCreate {
+            private_key_path: Some("onion.key".into()),
+            encrypt_key: false,
+            target_ip_address: None,
+        };
+        assert_eq!(
+            error_paths(&config),
+            [
+                "tor.stream-isolation",
+                "tor.socks",
+                "tor.onion",
+                "tor.onion.private-key-path"
+            ]
+        );
+
+        config.tor.use_tor_only = true;
+        config.tor.socks = "embassy:9050".to_owned();
+        config.tor.control = Some(TorControlConfig {
+            address: "embassy".to_owned(),
+            auth: TorControlAuth::Cookie,
+        });
+        config.tor.onion = OnionConfig::AutoCreate {
+            private_key_path: Some("/root/.lnd/onion.key".into()),
+            encrypt_key: true,
+            target_ip_address: None,
+        };
+        assert_eq!(error_paths(&config), ["tor.control.address"]);
+    }
+
+    #[test]
+    fn checks_autopilot_and_fee_allocation() {
+        let mut config = legacy_config();
+        config.autopilot.min_channel_size = config.autopilot.max_channel_size + 1;
+        config.advanced.max_channel_fee_allocation = 0.0;
+        assert_eq!(
+            error_paths(&config),
+            [
+                "autopilot.min-channel-size",
+                "advanced.max-channel-fee-allocation"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_sweeper_ratios() {
+        let mut config = legacy_config();
+        config.advanced.sweeper.sweeper_budget_tolocalratio = 1.5;
+        config.advanced.sweeper.sweeper_budget_nodeadlinehtlcratio = -0.1;
+        assert_eq!(
+            error_paths(&config),
+            [
+                "advanced.sweeper.sweeper-budget-tolocalratio",
+                "advanced.sweeper.sweeper-budget-nodeadlinehtlcratio"
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_ports() {
+        let mut config = legacy_config();
+        assert!(error_paths(&config).is_empty());
+        config.ports.watchtower = 9912;
+        config.ports.rest = config.ports.rpc;
+        assert_eq!(error_paths(&config), ["ports.rest", "ports.watchtower"]);
+        assert!(error_messages(&config)[0].starts_with("ports.rest: must be 8080: "));
+    }
+
+    #[test]
+    fn checks_gossip() {
+        let mut config = legacy_config();
+        config.gossip.max_channel_update_burst = 0;
+        config.gossip.msg_burst_bytes = config.gossip.msg_rate_bytes - 1;
+        assert_eq!(
+            error_paths(&config),
+            ["gossip.max-channel-update-burst", "gossip.msg-burst-bytes"]
+        );
+    }
+
+    #[test]
+    fn checks_routing() {
+        let mut config = legacy_config();
+        config.routing.min_route_probability = 2.0;
+        if let Estimator::Apriori(apriori) = &mut config.routing.estimator {
+            apriori.capacity_fraction = 0.5;
+            apriori.penalty_half_life = 0;
+        }
+        assert_eq!(
+            error_paths(&config),
+            [
+                "routing.min-route-probability",
+                "routing.estimator.penalty-half-life",
+                "routing.estimator.capacity-fraction"
+            ]
+        );
+
+        config.routing.min_route_probability = 0.01;
+        config.routing.estimator = Estimator::Bimodal(BimodalEstimator {
+            scale: 0,
+            ..BimodalEstimator::default()
+        });
+        assert_eq!(error_paths(&config), ["routing.estimator.scale"]);
+    }
+}
@@ -0,0 +1,599 @@
+//! lnd reports how far it got with starting up through its State service: NON_EXISTING or LOCKED
+//! once the wallet unlocker is up, then UNLOCKED, RPC_ACTIVE and finally SERVER_ACTIVE. We follow
+//! it to decide whether to create, unlock or re-key the wallet, and give up instead of retrying
+//! forever when lnd stalls. Our own progress goes to [`PHASE_PATH`] for the health check.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use base32::Alphabet;
+use lnd_client::grpc::lnrpc::WalletState;
+use lnd_client::grpc::{GrpcClient, StateSubscription};
+
+const LND_DIR: &str = "/root/.lnd";
+/// Read by the health check: the current [`Phase`] on the first line, followed by the error once
+/// it is `failed`. Relative to `/root/.lnd`, like the paths below.
+const PHASE_PATH: &str = "start9/walletPhase.txt";
+const PASSWORD_PATH: &str = "pwd.dat";
+const SEED_PATH: &str = "start9/cipherSeedMnemonic.txt";
+
+/// How long lnd may take to get through the steps [`start`] waits for.
+#[derive(Clone, Copy, Debug)]
+struct Timeouts {
+    /// Opening its database and serving the wallet unlocker.
+    unlocker: Duration,
+    /// From an unlocked wallet to a running server. Database migrations and waiting on the chain
+    /// backend happen here.
+    server: Duration,
+}
+
+const TIMEOUTS: Timeouts = Timeouts {
+    unlocker: Duration::from_secs(10 * 60),
+    server: Duration::from_secs(30 * 60),
+};
+
+/// The calls [`start`] makes to lnd, so tests can stand in for it.
+trait Lnd {
+    fn subscribe_state(&self) -> Result<Box<dyn States + '_>, lnd_client::Error>;
+    fn gen_seed(&self) -> Result<Vec<String>, lnd_client::Error>;
+    fn init_wallet(&self, password: &[u8], mnemonic: &[String]) -> Result<(), lnd_client::Error>;
+    fn unlock_wallet(
+        &self,
+        password: &[u8],
+        recovery_window: Option<i32>,
+    ) -> Result<(), lnd_client::Error>;
+    fn change_password(&self, current: &[u8], new: &[u8]) -> Result<(), lnd_client::Error>;
+}
+
+/// A subscription to lnd's state, see [`StateSubscription::next`].
+trait States {
+    fn next(&mut self, timeout: Duration) -> Result<Option<WalletState>, lnd_client::Error>;
+}
+
+impl States for StateSubscription<'_> {
+    fn next(&mut self, timeout: Duration) -> Result<Option<WalletState>, lnd_client::Error> {
+        StateSubscription::next(self, timeout)
+    }
+}
+
+impl Lnd for GrpcClient {
+    fn subscribe_state(&self) -> Result<Box<dyn States + '_>, lnd_client::Error> {
+        Ok(Box::new(GrpcClient::subscribe_state(self)?))
+    }
+
+    fn gen_seed(&self) -> Result<Vec<String>, lnd_client::Error> {
+        GrpcClient::gen_seed(self)
+    }
+
+    fn init_wallet(&self, password: &[u8], mnemonic: &[String]) -> Result<(), lnd_client::Error> {
+        GrpcClient::init_wallet(self, password, mnemonic)
+    }
+
+    fn unlock_wallet(
+        &self,
+        password: &[u8],
+        recovery_window: Option<i32>,
+    ) -> Result<(), lnd_client::Error> {
+        GrpcClient::unlock_wallet(self, password, recovery_window)
+    }
+
+    fn change_password(&self, current: &[u8], new: &[u8]) -> Result<(), lnd_client::Error> {
+        GrpcClient::change_password(self, current, new)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    WaitingForLnd,
+    CreatingWallet,
+    UnlockingWallet,
+    ConvertingPassword,
+    StartingServer,
+    Ready,
+    Failed,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::WaitingForLnd => "waiting-for-lnd",
+            Phase::CreatingWallet => "creating-wallet",
+            Phase::UnlockingWallet => "unlocking-wallet",
+            Phase::ConvertingPassword => "converting-password",
+            Phase::StartingServer => "starting-server",
+            Phase::Ready => "ready",
+            Phase::Failed => "failed",
+        }
+    }
+}
+
+/// How the wallet came to be unlocked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wallet {
+    /// Created just now from a new seed.
+    Created,
+    /// An existing wallet, unlocked by us or already before.
+    Opened,
+}
+
+/// Records `phase`. Failing to is only logged, since nothing but the health check depends on it.
+pub fn set_phase(phase: Phase, error: Option<&str>) {
+    write_phase(Path::new(LND_DIR), phase, error)
+}
+
+fn write_phase(lnd_dir: &Path, phase: Phase, error: Option<&str>) {
+    let mut contents = format!("{}\n", phase.as_str());
+    if let Some(error) = error {
+        contents.push_str(error);
+        contents.push('\n');
+    }
+    let path = lnd_dir.join(PHASE_PATH);
+    let tmp = path.with_extension("txt.tmp");
+    if let Err(e) = std::fs::write(&tmp, contents).and_then(|()| std::fs::rename(&tmp, &path)) {
+        eprintln!("Failed to record wallet phase {}: {}", phase.as_str(), e);
+    }
+}
+
+/// Follows lnd's state across dropped subscriptions, e.g. when lnd restarts its RPC server.
+struct StateWatcher<'a> {
+    lnd: &'a dyn Lnd,
+    subscription: Option<Box<dyn States + 'a>>,
+    state: Option<WalletState>,
+}
+
+impl<'a> StateWatcher<'a> {
+    fn new(lnd: &'a dyn Lnd) -> Self {
+        StateWatcher {
+            lnd,
+            subscription: None,
+            state: None,
+        }
+    }
+
+    /// Blocks until lnd reports a state `done` accepts, failing after `timeout`.
+    fn wait_for(
+        &mut self,
+        what: &str,
+        timeout: Duration,
+        done: impl Fn(WalletState) -> bool,
+    ) -> Result<WalletState, anyhow::Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::ZERO {
+                anyhow::bail!(
+                    "lnd did not bring up {} within {} (last state: {})",
+                    what,
+                    format_timeout(timeout),
+                    self.state.map_or("unknown", |state| state.as_str_name())
+                );
+            }
+            let subscription = match &mut self.subscription {
+                Some(subscription) => subscription,
+                None => match self.lnd.subscribe_state() {
+                    Ok(subscription) => self.subscription.insert(subscription),
+                    Err(e) if e.is_starting() => {
+                        std::thread::sleep(Duration::from_secs(1).min(remaining));
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                },
+            };
+            match subscription.next(remaining) {
+                Ok(Some(state)) => {
+                    if self.state != Some(state) {
+                        println!("lnd state: {}", state.as_str_name());
+                    }
+                    self.state = Some(state);
+                    if done(state) {
+                        return Ok(state);
+                    }
+                }
+                Ok(None) => self.subscription = None,
+                Err(e) if e.is_starting() => {
+                    self.subscription = None;
+                    std::thread::sleep(Duration::from_secs(1).min(remaining));
+                }
+                Err(_) if Instant::now() >= deadline => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Creates or unlocks lnd's wallet, whichever its state calls for, and waits for the server to
+/// come up.
+pub fn start(grpc: &GrpcClient, recovery_window: Option<i32>) -> Result<Wallet, anyhow::Error> {
+    run(grpc, Path::new(LND_DIR), TIMEOUTS, recovery_window)
+}
+
+fn run(
+    lnd: &dyn Lnd,
+    lnd_dir: &Path,
+    timeouts: Timeouts,
+    recovery_window: Option<i32>,
+) -> Result<Wallet, anyhow::Error> {
+    let result = start_inner(lnd, lnd_dir, timeouts, recovery_window);
+    match &result {
+        Ok(_) => write_phase(lnd_dir, Phase::Ready, None),
+        Err(e) => write_phase(lnd_dir, Phase::Failed, Some(&e.to_string())),
+    }
+    result
+}
+
+fn format_timeout(duration: Duration) -> String {
+    match duration.as_secs() / 60 {
+        0 => format!("{} ms", duration.as_millis()),
+        minutes => format!("{} minutes", minutes),
+    }
+}
+
+fn start_inner(
+    lnd: &dyn Lnd,
+    lnd_dir: &Path,
+    timeouts: Timeouts,
+    recovery_window: Option<i32>,
+) -> Result<Wallet, anyhow::Error> {
+    let mut states = StateWatcher::new(lnd);
+    write_phase(lnd_dir, Phase::WaitingForLnd, None);
+    let state = states.wait_for("the wallet unlocker", timeouts.unlocker, |state| {
+        state != WalletState::WaitingToStart
+    })?;
+    let password_path = lnd_dir.join(PASSWORD_PATH);
+    let password = match std::fs::read(&password_path) {
+        Ok(password) => Some(password),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+    let wallet = match (state, password) {
+        (WalletState::NonExisting, None) => {
+            write_phase(lnd_dir, Phase::CreatingWallet, None);
+            create(lnd, lnd_dir)?;
+            Wallet::Created
+        }
+        (WalletState::NonExisting, Some(_)) => anyhow::bail!(
+            "lnd has no wallet, but {} exists. Restore the LND backup that belongs to it.",
+            password_path.display()
+        ),
+        (WalletState::Locked, None) => anyhow::bail!(
+            "lnd's wallet is locked and there is no {} to unlock it with",
+            password_path.display()
+        ),
+        (WalletState::Locked, Some(password)) if !pw_is_typeable(&password) => {
+            write_phase(lnd_dir, Phase::ConvertingPassword, None);
+            convert_password(lnd, lnd_dir, &password)?;
+            Wallet::Opened
+        }
+        (WalletState::Locked, Some(password)) => {
+            write_phase(lnd_dir, Phase::UnlockingWallet, None);
+            lnd.unlock_wallet(&password, recovery_window)
+                .map_err(|e| anyhow::anyhow!("Error unlocking wallet: {}", e))?;
+            Wallet::Opened
+        }
+        (state, _) => {
+            println!("wallet already unlocked ({})", state.as_str_name());
+            Wallet::Opened
+        }
+    };
+    write_phase(lnd_dir, Phase::StartingServer, None);
+    states.wait_for("the RPC server", timeouts.server, |state| {
+        state == WalletState::ServerActive
+    })?;
+    Ok(wallet)
+}
+
+fn pw_is_typeable(pw: &[u8]) -> bool {
+    pw.iter().all(|&byte| (32..=126).contains(&byte)) // Space - ~
+}
+
+/// Unlocks the wallet while switching a password with untypeable bytes to its base32 encoding.
+fn convert_password(lnd: &dyn Lnd, lnd_dir: &Path, password: &[u8]) -> Result<(), anyhow::Error> {
+    let base_32_pw = base32::encode(Alphabet::RFC4648 { padding: false }, password);
+    lnd.change_password(password, base_32_pw.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Error converting the wallet password: {}", e))?;
+    let tmp = lnd_dir.join("new_pwd.dat");
+    std::fs::write(&tmp, &base_32_pw)?;
+    std::fs::rename(&tmp, lnd_dir.join(PASSWORD_PATH))?;
+    println!("Wallet password successfully converted to base32");
+    Ok(())
+}
+
+fn create(lnd: &dyn Lnd, lnd_dir: &Path) -> Result<(), anyhow::Error> {
+    println!("creating password data");
+    let password_bytes = {
+        let mut buf = [0; 16];
+        File::open("/dev/random")?.read_exact(&mut buf)?;
+        base32::encode(Alphabet::RFC4648 { padding: false }, &buf).into_bytes()
+    };
+
+    let cipher_seed_mnemonic = lnd
+        .gen_seed()
+        .map_err(|e| anyhow::anyhow!("Error generating seed: {}", e))?;
+    println!("CipherSeed successfully generated");
+
+    let seed_path = lnd_dir.join(SEED_PATH);
+    if let Err(err) = save_to_file(&cipher_seed_mnemonic, &seed_path) {
+        eprintln!("Failed to save the CipherSeedMnemonic: {}", err);
+    } else {
+        println!("CipherSeedMnemonic saved to '{}'", seed_path.display());
+    }
+
+    lnd.init_wallet(&password_bytes, &cipher_seed_mnemonic)
+        .map_err(|e| anyhow::anyhow!("Error creating wallet: {}", e))?;
+    std::fs::write(lnd_dir.join(PASSWORD_PATH), &password_bytes)?;
+    Ok(())
+}
+
+fn save_to_file(cipher_seed_mnemonic: &[String], file_path: &Path) -> io::Result<()> {
+    let mut file = File::create(file_path)?;
+    for (i, word) in cipher_seed_mnemonic.iter().enumerate() {
+        writeln!(file, "{} {}", i + 1, word)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use lnd_client::grpc::lnrpc::WalletState::{
+        self, Locked, NonExisting, RpcActive, ServerActive, Unlocked, WaitingToStart,
+    };
+    use tonic::Status;
+
+    use super::{run, Lnd, States, Timeouts, Wallet, PASSWORD_PATH, PHASE_PATH, SEED_PATH};
+
+    const TIMEOUTS: Timeouts = Timeouts {
+        unlocker: Duration::from_millis(300),
+        server: Duration::from_millis(300),
+    };
+
+    /// What a fake state subscription does next.
+    #[derive(Clone, Copy)]
+    enum Step {
+        State(WalletState),
+        /// lnd closes the stream, e.g. when it restarts its RPC server.
+        Close,
+        /// lnd's RPC server went away.
+        Unavailable,
+    }
+
+    /// Plays back `steps`, then reports nothing until the timeout, like an lnd that got stuck.
+    struct FakeStates(VecDeque<Step>);
+
+    impl States for FakeStates {
+        fn next(&mut self, timeout: Duration) -> Result<Option<WalletState>, lnd_client::Error> {
+            match self.0.pop_front() {
+                Some(Step::State(state)) => Ok(Some(state)),
+                Some(Step::Close) => Ok(None),
+                Some(Step::Unavailable) => Err(Status::unavailable("connection reset").into()),
+                None => {
+                    std::thread::sleep(timeout);
+                    Err(Status::deadline_exceeded("no state change").into())
+                }
+            }
+        }
+    }
+
+    /// Stands in for lnd: every subscription plays the next script in `subscriptions`, and every
+    /// wallet call is recorded in `calls`.
+    struct FakeLnd {
+        subscriptions: RefCell<VecDeque<Result<Vec<Step>, Status>>>,
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl FakeLnd {
+        fn new(subscriptions: Vec<Result<Vec<Step>, Status>>) -> Self {
+            FakeLnd {
+                subscriptions: RefCell::new(subscriptions.into()),
+                calls: RefCell::default(),
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.borrow().clone()
+        }
+    }
+
+    impl Lnd for FakeLnd {
+        fn subscribe_state(&self) -> Result<Box<dyn States + '_>, lnd_client::Error> {
+            match self.subscriptions.borrow_mut().pop_front() {
+                Some(Ok(steps)) => Ok(Box::new(FakeStates(steps.into()))),
+                Some(Err(status)) => Err(status.into()),
+                None => Err(Status::unavailable("lnd is gone").into()),
+            }
+        }
+
+        fn gen_seed(&self) -> Result<Vec<String>, lnd_client::Error> {
+            self.calls.borrow_mut().push("gen_seed".to_owned());
+            Ok(vec!["abandon".to_owned(), "ability".to_owned()])
+        }
+
+        fn init_wallet(
+            &self,
+            password: &[u8],
+            mnemonic: &[String],
+        ) -> Result<(), lnd_client::Error> {
+            self.calls.borrow_mut().push(format!(
+                "init_wallet {} {}",
+                password.len(),
+                mnemonic.join(" ")
+            ));
+            Ok(())
+        }
+
+        fn unlock_wallet(
+            &self,
+            password: &[u8],
+            recovery_window: Option<i32>,
+        ) -> Result<(), lnd_client::Error> {
+            self.calls.borrow_mut().push(format!(
+                "unlock_wallet {} {:?}",
+                String::from_utf8_lossy(password),
+                recovery_window
+            ));
+            Ok(())
+        }
+
+        fn change_password(&self, current: &[u8], new: &[u8]) -> Result<(), lnd_client::Error> {
+            self.calls.borrow_mut().push(format!(
+                "change_password {:?} {}",
+                current,
+                String::from_utf8_lossy(new)
+            ));
+            Ok(())
+        }
+    }
+
+    fn lnd_dir(password: Option<&[u8]>) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("start9")).unwrap();
+        if let Some(password) = password {
+            std::fs::write(dir.path().join(PASSWORD_PATH), password).unwrap();
+        }
+        dir
+    }
+
+    fn phase(lnd_dir: &Path) -> String {
+        std::fs::read_to_string(lnd_dir.join(PHASE_PATH)).unwrap()
+    }
+
+    fn states(states: &[WalletState]) -> Vec<Step> {
+        states.iter().copied().map(Step::State).collect()
+    }
+
+    #[test]
+    fn creates_a_wallet_when_there_is_none() {
+        let dir = lnd_dir(None);
+        let lnd = FakeLnd::new(vec![Ok(states(&[
+            WaitingToStart,
+            NonExisting,
+            Unlocked,
+            RpcActive,
+            ServerActive,
+        ]))]);
+        assert_eq!(
+            run(&lnd, dir.path(), TIMEOUTS, None).unwrap(),
+            Wallet::Created
+        );
+        assert_eq!(lnd.calls(), ["gen_seed", "init_wallet 26 abandon ability"]);
+        assert_eq!(
+            std::fs::read(dir.path().join(PASSWORD_PATH)).unwrap().len(),
+            26
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(SEED_PATH)).unwrap(),
+            "1 abandon\n2 ability\n"
+        );
+        assert_eq!(phase(dir.path()), "ready\n");
+    }
+
+    #[test]
+    fn refuses_to_create_a_wallet_over_a_stored_password() {
+        let dir = lnd_dir(Some(b"password"));
+        let lnd = FakeLnd::new(vec![Ok(states(&[NonExisting]))]);
+        let err = run(&lnd, dir.path(), TIMEOUTS, None).unwrap_err();
+        assert!(
+            err.to_string().starts_with("lnd has no wallet, but "),
+            "{}",
+            err
+        );
+        assert!(lnd.calls().is_empty());
+        assert!(phase(dir.path()).starts_with("failed\nlnd has no wallet, but "));
+        // the password of the wallet the user has to restore is left alone
+        assert_eq!(
+            std::fs::read(dir.path().join(PASSWORD_PATH)).unwrap(),
+            b"password"
+        );
+    }
+
+    #[test]
+    fn unlocks_a_locked_wallet() {
+        let dir = lnd_dir(Some(b"password"));
+        let lnd = FakeLnd::new(vec![Ok(states(&[
+            Locked,
+            Unlocked,
+            RpcActive,
+            ServerActive,
+        ]))]);
+        assert_eq!(
+            run(&lnd, dir.path(), TIMEOUTS, Some(2500)).unwrap(),
+            Wallet::Opened
+        );
+        assert_eq!(lnd.calls(), ["unlock_wallet password Some(2500)"]);
+
+        let dir = lnd_dir(None);
+        let lnd = FakeLnd::new(vec![Ok(states(&[Locked]))]);
+        let err = run(&lnd, dir.path(), TIMEOUTS, None).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("lnd's wallet is locked and there is no "));
+    }
+
+    #[test]
+    fn converts_an_untypeable_password() {
+        let dir = lnd_dir(Some(&[0, 255]));
+        let lnd = FakeLnd::new(vec![Ok(states(&[Locked, ServerActive]))]);
+        run(&lnd, dir.path(), TIMEOUTS, None).unwrap();
+        assert_eq!(lnd.calls(), ["change_password [0, 255] AD7Q"]);
+        assert_eq!(
+            std::fs::read(dir.path().join(PASSWORD_PATH)).unwrap(),
+            b"AD7Q"
+        );
+    }
+
+    #[test]
+    fn resubscribes_when_the_stream_closes() {
+        let dir = lnd_dir(Some(b"password"));
+        let lnd = FakeLnd::new(vec![
+            Ok(vec![Step::State(WaitingToStart), Step::Close]),
+            // lnd is restarting its RPC server
+            Err(Status::unavailable("connection refused")),
+            Ok(vec![Step::State(Locked), Step::Unavailable]),
+            Ok(states(&[Unlocked, ServerActive])),
+        ]);
+        let timeouts = Timeouts {
+            unlocker: Duration::from_secs(5),
+            server: Duration::from_secs(5),
+        };
+        assert_eq!(
+            run(&lnd, dir.path(), timeouts, None).unwrap(),
+            Wallet::Opened
+        );
+        assert_eq!(lnd.calls(), ["unlock_wallet password None"]);
+        assert!(lnd.subscriptions.borrow().is_empty());
+    }
+
+    #[test]
+    fn fails_on_errors_other_than_a_starting_lnd() {
+        let dir = lnd_dir(None);
+        let lnd = FakeLnd::new(vec![Err(Status::permission_denied("bad macaroon"))]);
+        let err = run(&lnd, dir.path(), TIMEOUTS, None).unwrap_err();
+        assert!(err.to_string().contains("bad macaroon"), "{}", err);
+    }
+
+    #[test]
+    fn gives_up_when_lnd_stalls() {
+        let dir = lnd_dir(None);
+        let lnd = FakeLnd::new(vec![Ok(states(&[WaitingToStart]))]);
+        let err = run(&lnd, dir.path(), TIMEOUTS, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "lnd did not bring up the wallet unlocker within 300 ms (last state: WAITING_TO_START)"
+        );
+        assert!(phase(dir.path()).starts_with("failed\n"));
+
+        let dir = lnd_dir(Some(b"password"));
+        let lnd = FakeLnd::new(vec![Ok(states(&[Locked, Unlocked, RpcActive]))]);
+        let err = run(&lnd, dir.path(), TIMEOUTS, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "lnd did not bring up the RPC server within 300 ms (last state: RPC_ACTIVE)"
+        );
+    }
+}
@@ -15,6 +15,7 @@ export HOST_IP=$(ip -4 route list match 0/0 | awk '{print $3}')
 export CONTAINER_IP=$(ifconfig | sed -En 's/127.0.0.1//;s/.*inet (addr:)?(([0-9]*\.){3}[0-9]*).*/\2/p')
 export PEER_TOR_ADDRESS=$(yq e '.peer-tor-address' /root/.lnd/start9/config.yaml)
 export CONTROL_TOR_ADDRESS=$(yq e '.control-tor-address' /root/.lnd/start9/config.yaml)
+export NETWORK=$(yq e '.network // "mainnet"' /root/.lnd/start9/config.yaml)
 
 mkdir -p /root/.lnd/start9/ && mkdir -p /root/.lnd/public
 echo $PEER_TOR_ADDRESS > /root/.lnd/start9/peerTorAddress
@@ -46,13 +47,13 @@ else
 fi
 lnd_child=$!
 
-while ! [ -e /root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon ]; do
+while ! [ -e /root/.lnd/data/chain/bitcoin/$NETWORK/admin.macaroon ]; do
   echo "Waiting for lnd to create macaroon..."
   sleep 30
 done
 
-cat /root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon | basenc --base16 -w0  > /root/.lnd/start9/admin.macaroon.hex
-cat /root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon | basenc --base64url -w0  > /root/.lnd/start9/admin.macaroon.base64url
+cat /root/.lnd/data/chain/bitcoin/$NETWORK/admin.macaroon | basenc --base16 -w0  > /root/.lnd/start9/admin.macaroon.hex
+cat /root/.lnd/data/chain/bitcoin/$NETWORK/admin.macaroon | basenc --base64url -w0  > /root/.lnd/start9/admin.macaroon.base64url
 
 trap _term SIGTERM
 
@@ -1,10 +1,60 @@
+use lnd_client::{LndClient, WalletState};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(serde::Deserialize, Debug)]
-pub struct LndGetInfoRes {
-    synced_to_chain: bool,
-    synced_to_graph: bool,
+/// StartOS runs the health check every few seconds, so a hanging lnd must not block it for long.
+const GETINFO_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Network {
+    #[default]
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    fn chain_dir(&self) -> PathBuf {
+        Path::new("/root/.lnd/data/chain/bitcoin").join(match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        })
+    }
+}
+
+/// The subset of the configurator's `config.yaml` the health check cares about.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Config {
+    #[serde(default)]
+    network: Network,
+    #[serde(default)]
+    ports: Ports,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Ports {
+    #[serde(default = "default_rest_port")]
+    rest: u16,
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Ports {
+            rest: default_rest_port(),
+        }
+    }
+}
+
+fn default_rest_port() -> u16 {
+    8080
 }
 
 pub enum HealthCheckResult {
@@ -16,7 +66,11 @@ pub enum HealthCheckResult {
 }
 
 fn main() {
-    std::process::exit(match run_health_checks() {
+    let result = match std::env::args().nth(1).as_deref() {
+        Some("prune") => check_prune(),
+        _ => run_health_checks(),
+    };
+    std::process::exit(match result {
         Ok(result) => {
             eprintln!("{}", result.message.unwrap_or_default());
             result.code
@@ -34,36 +88,66 @@ pub struct HealthCheckRes {
     pub message: Option<String>,
 }
 
+/// Reports the configurator's progress with the wallet until lnd's server is up, if known.
+fn check_wallet_phase() -> Result<Option<HealthCheckRes>, anyhow::Error> {
+    let phase = match std::fs::read_to_string("/root/.lnd/start9/walletPhase.txt") {
+        Ok(phase) => phase,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut lines = phase.lines();
+    let starting = |message: &str| {
+        Some(HealthCheckRes {
+            code: 60,
+            message: Some(message.to_string()),
+        })
+    };
+    Ok(match lines.next().unwrap_or_default() {
+        "waiting-for-lnd" => starting("Waiting for LND to start"),
+        "creating-wallet" => starting("Creating wallet"),
+        "unlocking-wallet" => starting("Unlocking wallet"),
+        "converting-password" => starting("Converting wallet password"),
+        "starting-server" => starting("Wallet unlocked, starting RPC server"),
+        "failed" => Some(HealthCheckRes {
+            code: 1,
+            message: Some(lines.collect::<Vec<_>>().join("\n")),
+        }),
+        _ => None,
+    })
+}
+
 fn run_health_checks() -> Result<HealthCheckRes, anyhow::Error> {
-    while !Path::new("/root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon").exists() {
+    if let Some(result) = check_wallet_phase()? {
+        return Ok(result);
+    }
+    let config: Config = serde_yaml::from_reader(File::open("/root/.lnd/start9/config.yaml")?)?;
+    let macaroon_path = config.network.chain_dir().join("admin.macaroon");
+    if !macaroon_path.exists() {
         return Ok(HealthCheckRes {
             code: 60,
             message: None,
         });
     }
 
-    let mac = std::fs::read(Path::new(
-        "/root/.lnd/data/chain/bitcoin/mainnet/admin.macaroon",
-    ))?;
-
-    let mac_encoded = hex::encode_upper(mac);
-    let node_info: Result<LndGetInfoRes, anyhow::Error> = {
-        serde_json::from_slice(
-            &std::process::Command::new("curl")
-                .arg("--no-progress-meter")
-                .arg("--header")
-                .arg(format!("Grpc-Metadata-macaroon: {}", mac_encoded))
-                .arg("--cacert")
-                .arg("/root/.lnd/tls.cert")
-                .arg("https://lnd.embassy:8080/v1/getinfo")
-                .output()?
-                .stdout,
-        )
-        .map_err(|e| e.into())
-    };
+    let node_info = LndClient::new(
+        "lnd.embassy",
+        config.ports.rest,
+        lnd_client::TLS_CERT_PATH,
+        GETINFO_TIMEOUT,
+    )
+    .and_then(|client| client.with_macaroon(&macaroon_path))
+    // getinfo only answers once the server is active
+    .and_then(|client| match client.state()? {
+        WalletState::ServerActive => client.get_info().map(Ok),
+        state => Ok(Err(state)),
+    });
 
     match node_info {
-        Ok(r) => match () {
+        Ok(Err(state)) => Ok(HealthCheckRes {
+            code: 60,
+            message: Some(format!("LND is starting ({:?})", state)),
+        }),
+        Ok(Ok(r)) => match () {
             () if r.synced_to_graph && r.synced_to_chain => Ok(HealthCheckRes {
                 code: 0,
                 message: None,
@@ -90,3 +174,18 @@ fn run_health_checks() -> Result<HealthCheckRes, anyhow::Error> {
         }
     }
 }
+
+/// Fails with the configurator's warning when bitcoind has pruned blocks lnd may need.
+fn check_prune() -> Result<HealthCheckRes, anyhow::Error> {
+    match std::fs::read_to_string("/root/.lnd/start9/pruneWarning.txt") {
+        Ok(warning) => Ok(HealthCheckRes {
+            code: 1,
+            message: Some(warning),
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HealthCheckRes {
+            code: 0,
+            message: None,
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,23 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    {
+        // so building does not depend on a protoc install
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+        tonic_build::configure()
+            .build_server(false)
+            // the generated `connect` needs the 2021 prelude, and we connect through `GrpcClient`
+            .build_transport(false)
+            // trimmed copies of lnd's protos, `make protos` swaps in the upstream ones
+            .compile(
+                &[
+                    "proto/lightning.proto",
+                    "proto/walletunlocker.proto",
+                    "proto/stateservice.proto",
+                    "proto/watchtowerrpc/watchtower.proto",
+                    "proto/wtclientrpc/wtclient.proto",
+                ],
+                &["proto"],
+            )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::fmt::{self, Display};
+
+/// gRPC's `UNAVAILABLE` status code, which lnd answers calls its RPC server is not ready for with.
+const UNAVAILABLE: i32 = 14;
+
+/// Why a call to lnd's REST API failed.
+#[derive(Debug)]
+pub enum Error {
+    /// `tls.cert` or the macaroon could not be read.
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// `tls.cert` holds no usable certificate, or lnd presented a different one.
+    Tls(String),
+    /// lnd did not answer in time.
+    Timeout(reqwest::Error),
+    /// lnd could not be reached, e.g. because it is not listening yet.
+    Connect(reqwest::Error),
+    /// Any other transport failure.
+    Http(reqwest::Error),
+    /// lnd answered with an RPC error.
+    Lnd {
+        status: u16,
+        code: i32,
+        message: String,
+    },
+    /// lnd answered with something other than the expected JSON.
+    Decode { status: u16, body: String },
+    /// lnd answered a gRPC call with an error status.
+    #[cfg(feature = "grpc")]
+    Status(Box<tonic::Status>),
+    /// The gRPC connection to lnd could not be set up.
+    #[cfg(feature = "grpc")]
+    Transport(tonic::transport::Error),
+}
+
+impl Error {
+    /// lnd is up but not ready for this call yet, so retrying later may succeed.
+    pub fn is_starting(&self) -> bool {
+        match self {
+            Error::Connect(_) => true,
+            Error::Lnd { status, code, .. } => *status == 503 || *code == UNAVAILABLE,
+            #[cfg(feature = "grpc")]
+            Error::Status(status) => status.code() == tonic::Code::Unavailable,
+            #[cfg(feature = "grpc")]
+            Error::Transport(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The TLS failure behind a failed connection, if any. rustls errors reach us wrapped in an
+/// `io::Error`, which hides them from `source()`.
+fn tls_failure<'a>(e: &'a (dyn std::error::Error + 'static)) -> Option<&'a rustls::Error> {
+    let mut source = Some(e);
+    while let Some(e) = source {
+        if let Some(tls) = e.downcast_ref::<rustls::Error>() {
+            return Some(tls);
+        }
+        source = match e.downcast_ref::<std::io::Error>() {
+            Some(io) => io
+                .get_ref()
+                .map(|inner| inner as &(dyn std::error::Error + 'static)),
+            None => e.source(),
+        };
+    }
+    None
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if let Some(tls) = tls_failure(&e) {
+            Error::Tls(tls.to_string())
+        } else if e.is_timeout() {
+            Error::Timeout(e)
+        } else if e.is_connect() {
+            Error::Connect(e)
+        } else {
+            Error::Http(e)
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::transport::Error> for Error {
+    fn from(e: tonic::transport::Error) -> Self {
+        match tls_failure(&e) {
+            Some(tls) => Error::Tls(tls.to_string()),
+            None => Error::Transport(e),
+        }
+    }
+}
+
+#[cfg(feature = "grpc")]
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Status(Box::new(status))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "cannot read {}: {}", path, source),
+            Error::Tls(reason) => write!(f, "TLS with lnd failed: {}", reason),
+            Error::Timeout(e) => write!(f, "lnd did not answer in time: {}", e),
+            Error::Connect(e) => write!(f, "cannot connect to lnd: {}", e),
+            Error::Http(e) => write!(f, "request to lnd failed: {}", e),
+            Error::Lnd {
+                status,
+                code,
+                message,
+            } => write!(f, "lnd error (HTTP {}, code {}): {}", status, code, message),
+            Error::Decode { status, body } => {
+                write!(
+                    f,
+                    "unexpected response from lnd (HTTP {}): {}",
+                    status, body
+                )
+            }
+            #[cfg(feature = "grpc")]
+            Error::Status(status) => {
+                write!(f, "lnd error ({:?}): {}", status.code(), status.message())
+            }
+            #[cfg(feature = "grpc")]
+            Error::Transport(e) => {
+                write!(f, "cannot connect to lnd over gRPC: {}", e)?;
+                // tonic's own message is just "transport error", hyper's already include their
+                // cause
+                let mut shown = e.to_string();
+                let mut source = std::error::Error::source(e);
+                while let Some(e) = source {
+                    let message = e.to_string();
+                    if !shown.contains(&message) {
+                        write!(f, ": {}", message)?;
+                    }
+                    shown = message;
+                    source = e.source();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Timeout(e) | Error::Connect(e) | Error::Http(e) => Some(e),
+            #[cfg(feature = "grpc")]
+            Error::Status(status) => Some(status.as_ref()),
+            #[cfg(feature = "grpc")]
+            Error::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
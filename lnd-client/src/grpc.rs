@@ -0,0 +1,263 @@
+//! Blocking wrappers around lnd's gRPC services, for the calls the REST API does not cover as
+//! well. Connections pin `tls.cert` the same way the REST client does, and errors keep lnd's
+//! gRPC status code.
+
+use std::convert::TryFrom;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::ServerName;
+use tokio::net::TcpStream;
+use tokio::runtime::Runtime;
+use tokio_rustls::TlsConnector;
+use tonic::codegen::InterceptedService;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Request, Status, Streaming};
+
+use crate::{read, tls, Error};
+
+pub mod lnrpc {
+    tonic::include_proto!("lnrpc");
+}
+
+pub mod watchtowerrpc {
+    tonic::include_proto!("watchtowerrpc");
+}
+
+pub mod wtclientrpc {
+    tonic::include_proto!("wtclientrpc");
+}
+
+use lnrpc::lightning_client::LightningClient;
+use lnrpc::state_client::StateClient;
+use lnrpc::wallet_unlocker_client::WalletUnlockerClient;
+use lnrpc::WalletState;
+use watchtowerrpc::watchtower_client::WatchtowerClient;
+use wtclientrpc::watchtower_client_client::WatchtowerClientClient;
+
+/// Adds the hex encoded macaroon lnd expects in the `macaroon` metadata to every call.
+#[derive(Clone)]
+pub struct MacaroonInterceptor(Option<MetadataValue<Ascii>>);
+
+impl Interceptor for MacaroonInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(macaroon) = &self.0 {
+            request.metadata_mut().insert("macaroon", macaroon.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Service = InterceptedService<Channel, MacaroonInterceptor>;
+
+pub struct GrpcClient {
+    runtime: Runtime,
+    channel: Channel,
+    interceptor: MacaroonInterceptor,
+}
+
+impl GrpcClient {
+    /// Connects to lnd's gRPC server on `host:port`, only trusting the certificate at `tls_cert`.
+    /// Every call gives up after `timeout`.
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls_cert: impl AsRef<Path>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let mut config = tls::pinned_config(&read(tls_cert.as_ref())?)?;
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let connector = TlsConnector::from(Arc::new(config));
+        let server_name = ServerName::try_from(host)
+            .map_err(|e| Error::Tls(format!("invalid server name {}: {}", host, e)))?;
+        let address = format!("{}:{}", host, port);
+        // the connector below does TLS itself, hyper refuses https URIs without tonic's own TLS
+        let endpoint = Endpoint::from_shared(format!("http://{}", address))?
+            .connect_timeout(crate::CONNECT_TIMEOUT)
+            .timeout(timeout);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start a tokio runtime");
+        let channel = runtime.block_on(endpoint.connect_with_connector(tower::service_fn(
+            move |_: Uri| {
+                let connector = connector.clone();
+                let server_name = server_name.clone();
+                let address = address.clone();
+                async move {
+                    let tcp = TcpStream::connect(address).await?;
+                    connector.connect(server_name, tcp).await
+                }
+            },
+        )))?;
+        Ok(GrpcClient {
+            runtime,
+            channel,
+            interceptor: MacaroonInterceptor(None),
+        })
+    }
+
+    /// Authenticates every call with the macaroon at `path`.
+    pub fn with_macaroon(mut self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        let macaroon = hex::encode(read(path.as_ref())?);
+        self.interceptor =
+            MacaroonInterceptor(Some(macaroon.parse().expect("hex is valid ASCII metadata")));
+        Ok(self)
+    }
+
+    pub fn lightning(&self) -> LightningClient<Service> {
+        LightningClient::with_interceptor(self.channel.clone(), self.interceptor.clone())
+    }
+
+    pub fn wallet_unlocker(&self) -> WalletUnlockerClient<Service> {
+        WalletUnlockerClient::with_interceptor(self.channel.clone(), self.interceptor.clone())
+    }
+
+    pub fn state_service(&self) -> StateClient<Service> {
+        StateClient::with_interceptor(self.channel.clone(), self.interceptor.clone())
+    }
+
+    pub fn watchtower(&self) -> WatchtowerClient<Service> {
+        WatchtowerClient::with_interceptor(self.channel.clone(), self.interceptor.clone())
+    }
+
+    pub fn watchtower_client(&self) -> WatchtowerClientClient<Service> {
+        WatchtowerClientClient::with_interceptor(self.channel.clone(), self.interceptor.clone())
+    }
+
+    /// Runs a call of one of the service clients to completion.
+    pub fn block_on<T>(
+        &self,
+        call: impl std::future::Future<Output = Result<tonic::Response<T>, Status>>,
+    ) -> Result<T, Error> {
+        Ok(self.runtime.block_on(call)?.into_inner())
+    }
+
+    /// Follows lnd's state from the current one on. The State service works without a macaroon.
+    pub fn subscribe_state(&self) -> Result<StateSubscription<'_>, Error> {
+        let stream = self.block_on(
+            self.state_service()
+                .subscribe_state(lnrpc::SubscribeStateRequest::default()),
+        )?;
+        Ok(StateSubscription {
+            client: self,
+            stream,
+        })
+    }
+
+    /// A new aezeed mnemonic without a seed passphrase.
+    pub fn gen_seed(&self) -> Result<Vec<String>, Error> {
+        let response = self.block_on(
+            self.wallet_unlocker()
+                .gen_seed(lnrpc::GenSeedRequest::default()),
+        )?;
+        Ok(response.cipher_seed_mnemonic)
+    }
+
+    pub fn init_wallet(&self, password: &[u8], mnemonic: &[String]) -> Result<(), Error> {
+        self.block_on(
+            self.wallet_unlocker()
+                .init_wallet(lnrpc::InitWalletRequest {
+                    wallet_password: password.to_vec(),
+                    cipher_seed_mnemonic: mnemonic.to_vec(),
+                    ..Default::default()
+                }),
+        )?;
+        Ok(())
+    }
+
+    /// Unlocks the wallet, rescanning `recovery_window` addresses deep if set.
+    pub fn unlock_wallet(
+        &self,
+        password: &[u8],
+        recovery_window: Option<i32>,
+    ) -> Result<(), Error> {
+        self.block_on(
+            self.wallet_unlocker()
+                .unlock_wallet(lnrpc::UnlockWalletRequest {
+                    wallet_password: password.to_vec(),
+                    recovery_window: recovery_window.unwrap_or_default(),
+                }),
+        )?;
+        Ok(())
+    }
+
+    /// Changes the wallet password, which also unlocks the wallet.
+    pub fn change_password(&self, current: &[u8], new: &[u8]) -> Result<(), Error> {
+        self.block_on(
+            self.wallet_unlocker()
+                .change_password(lnrpc::ChangePasswordRequest {
+                    current_password: current.to_vec(),
+                    new_password: new.to_vec(),
+                }),
+        )?;
+        Ok(())
+    }
+
+    pub fn list_channels(&self) -> Result<Vec<lnrpc::Channel>, Error> {
+        let response = self.block_on(
+            self.lightning()
+                .list_channels(lnrpc::ListChannelsRequest::default()),
+        )?;
+        Ok(response.channels)
+    }
+
+    /// Restores the channels in a `channel.backup` file, after which lnd asks their peers to
+    /// force close them.
+    pub fn restore_channel_backups(&self, multi_chan_backup: Vec<u8>) -> Result<(), Error> {
+        self.block_on(
+            self.lightning()
+                .restore_channel_backups(lnrpc::RestoreChanBackupRequest {
+                    backup: Some(lnrpc::restore_chan_backup_request::Backup::MultiChanBackup(
+                        multi_chan_backup,
+                    )),
+                }),
+        )?;
+        Ok(())
+    }
+
+    /// This node's watchtower server, if enabled.
+    pub fn tower_info(&self) -> Result<watchtowerrpc::GetInfoResponse, Error> {
+        self.block_on(
+            self.watchtower()
+                .get_info(watchtowerrpc::GetInfoRequest::default()),
+        )
+    }
+
+    /// Backs up channel states to the watchtower `pubkey` at `address`.
+    pub fn add_tower(&self, pubkey: Vec<u8>, address: &str) -> Result<(), Error> {
+        self.block_on(
+            self.watchtower_client()
+                .add_tower(wtclientrpc::AddTowerRequest {
+                    pubkey,
+                    address: address.to_owned(),
+                }),
+        )?;
+        Ok(())
+    }
+}
+
+pub struct StateSubscription<'a> {
+    client: &'a GrpcClient,
+    stream: Streaming<lnrpc::SubscribeStateResponse>,
+}
+
+impl StateSubscription<'_> {
+    /// The next state lnd reports, or `None` once it closes the stream. Fails with
+    /// `DeadlineExceeded` if lnd reports nothing within `timeout`.
+    pub fn next(&mut self, timeout: Duration) -> Result<Option<WalletState>, Error> {
+        let stream = &mut self.stream;
+        // the timer has to be created inside the runtime
+        let message = self
+            .client
+            .runtime
+            .block_on(async { tokio::time::timeout(timeout, stream.message()).await })
+            .map_err(|_| {
+                Status::deadline_exceeded(format!("no state change within {}s", timeout.as_secs()))
+            })??;
+        Ok(message.map(|message| message.state()))
+    }
+}
@@ -0,0 +1,347 @@
+//! A typed client for the parts of lnd's REST API the health check uses.
+//! Every request pins lnd's `tls.cert`, and carries the macaroon once one is set. The `grpc`
+//! feature adds [`grpc::GrpcClient`] for lnd's gRPC services.
+
+mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod tls;
+
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::blocking::{Client, RequestBuilder};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+pub use error::Error;
+
+pub const TLS_CERT_PATH: &str = "/root/.lnd/tls.cert";
+/// How long a whole request may take unless the caller picks another timeout.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The parts of `GET /v1/getinfo` we use.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetInfo {
+    pub identity_pubkey: String,
+    pub alias: String,
+    pub block_height: u32,
+    pub synced_to_chain: bool,
+    pub synced_to_graph: bool,
+    /// `pubkey@host:port` for every address lnd advertises.
+    #[serde(default)]
+    pub uris: Vec<String>,
+}
+
+/// lnd's wallet and RPC server state, as reported by `GET /v1/state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WalletState {
+    NonExisting,
+    Locked,
+    Unlocked,
+    RpcActive,
+    ServerActive,
+    WaitingToStart,
+}
+
+#[derive(Deserialize)]
+struct StateResponse {
+    state: WalletState,
+}
+
+/// The body of an RPC error. grpc-gateway puts the call's gRPC status code in `code`.
+#[derive(Deserialize)]
+struct RpcError {
+    #[serde(default)]
+    code: i32,
+    message: String,
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+pub struct LndClient {
+    http: Client,
+    base_url: String,
+    /// Hex encoded, as the `Grpc-Metadata-macaroon` header wants it.
+    macaroon: Option<String>,
+}
+
+impl LndClient {
+    /// A client for lnd's REST API on `host:port` that only trusts the certificate at `tls_cert`
+    /// and gives up on a request after `timeout`.
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls_cert: impl AsRef<Path>,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let tls = tls::pinned_config(&read(tls_cert.as_ref())?)?;
+        let http = Client::builder()
+            .use_preconfigured_tls(tls)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(timeout)
+            .build()
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        Ok(LndClient {
+            http,
+            base_url: format!("https://{}:{}", host, port),
+            macaroon: None,
+        })
+    }
+
+    /// Authenticates every request with the macaroon at `path`. `state` works without one.
+    pub fn with_macaroon(mut self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        self.macaroon = Some(hex::encode(read(path.as_ref())?));
+        Ok(self)
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, format!("{}{}", self.base_url, path));
+        match &self.macaroon {
+            Some(macaroon) => request.header("Grpc-Metadata-macaroon", macaroon),
+            None => request,
+        }
+    }
+
+    fn send<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        let response = request.send()?;
+        let status = response.status();
+        let body = response.bytes()?;
+        if status.is_success() {
+            serde_json::from_slice(&body).map_err(|_| Error::Decode {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).into_owned(),
+            })
+        } else {
+            match serde_json::from_slice::<RpcError>(&body) {
+                Ok(RpcError { code, message }) => Err(Error::Lnd {
+                    status: status.as_u16(),
+                    code,
+                    message,
+                }),
+                Err(_) => Err(Error::Decode {
+                    status: status.as_u16(),
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                }),
+            }
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        self.send(self.request(Method::GET, path))
+    }
+
+    pub fn get_info(&self) -> Result<GetInfo, Error> {
+        self.get("/v1/getinfo")
+    }
+
+    pub fn state(&self) -> Result<WalletState, Error> {
+        Ok(self.get::<StateResponse>("/v1/state")?.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use super::{Error, LndClient, WalletState};
+
+    fn testdata(file: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/testdata")
+            .join(file)
+    }
+
+    /// A local stand-in for lnd's REST server that presents `testdata/tls.cert` and answers one
+    /// request with `status` and `body`. Sends the request's header lines to the returned channel.
+    fn lnd(status: &'static str, body: &'static str) -> (u16, mpsc::Receiver<Vec<String>>) {
+        let certs = rustls_pemfile::certs(&mut &std::fs::read(testdata("tls.cert")).unwrap()[..])
+            .unwrap()
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key = rustls_pemfile::pkcs8_private_keys(
+            &mut &std::fs::read(testdata("tls.key")).unwrap()[..],
+        )
+        .unwrap()
+        .remove(0);
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, rustls::PrivateKey(key))
+            .unwrap();
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let connection = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            let mut reader = BufReader::new(rustls::StreamOwned::new(connection, tcp));
+            // a client that rejects the certificate fails the handshake here
+            let mut head = Vec::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).is_err() {
+                    return;
+                }
+                match line.trim_end() {
+                    "" => break,
+                    line => head.push(line.to_owned()),
+                }
+            }
+            let length = head
+                .iter()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("content-length: ")?
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or(0);
+            reader.read_exact(&mut vec![0; length]).unwrap();
+            let stream = reader.get_mut();
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+            stream.conn.send_close_notify();
+            stream.flush().unwrap();
+            let _ = tx.send(head);
+        });
+        (port, rx)
+    }
+
+    fn client(port: u16, cert: &str) -> LndClient {
+        LndClient::new("127.0.0.1", port, testdata(cert), Duration::from_secs(5)).unwrap()
+    }
+
+    const GET_INFO: &str = r#"{"identity_pubkey":"02ab","alias":"node","block_height":800000,"synced_to_chain":true,"synced_to_graph":false,"uris":["02ab@203.0.113.7:9735"]}"#;
+
+    #[test]
+    fn accepts_only_the_pinned_certificate() {
+        let (port, _) = lnd("200 OK", GET_INFO);
+        let info = client(port, "tls.cert").get_info().unwrap();
+        assert_eq!(info.alias, "node");
+        assert_eq!(info.uris, ["02ab@203.0.113.7:9735"]);
+
+        let (port, requests) = lnd("200 OK", GET_INFO);
+        match client(port, "other.cert").get_info() {
+            Err(Error::Tls(reason)) => assert!(
+                reason.contains("other than the pinned tls.cert"),
+                "{}",
+                reason
+            ),
+            res => panic!("expected a TLS error, got {:?}", res.map(|info| info.alias)),
+        }
+        assert!(requests.recv().is_err());
+
+        assert!(matches!(
+            LndClient::new(
+                "127.0.0.1",
+                port,
+                testdata("tls.key"),
+                Duration::from_secs(5)
+            ),
+            Err(Error::Tls(_))
+        ));
+    }
+
+    #[test]
+    fn sends_the_macaroon_as_hex() {
+        let (port, requests) = lnd("200 OK", r#"{"state":"SERVER_ACTIVE"}"#);
+        assert_eq!(
+            client(port, "tls.cert").state().unwrap(),
+            WalletState::ServerActive
+        );
+        let head = requests.recv().unwrap();
+        assert_eq!(head[0], "GET /v1/state HTTP/1.1");
+        assert!(!head
+            .iter()
+            .any(|line| line.to_lowercase().starts_with("grpc-metadata-macaroon")));
+
+        let (port, requests) = lnd("200 OK", GET_INFO);
+        client(port, "tls.cert")
+            .with_macaroon(testdata("admin.macaroon"))
+            .unwrap()
+            .get_info()
+            .unwrap();
+        let head = requests.recv().unwrap();
+        assert!(
+            head.contains(&"grpc-metadata-macaroon: 0201036c6e6402aabbcc00ff".to_owned()),
+            "{:?}",
+            head
+        );
+    }
+
+    #[test]
+    fn decodes_lnd_errors() {
+        let (port, _) = lnd(
+            "503 Service Unavailable",
+            r#"{"code":14,"message":"the RPC server is in the process of starting up, but not yet ready to accept calls","details":[]}"#,
+        );
+        let err = client(port, "tls.cert").get_info().unwrap_err();
+        assert!(
+            matches!(&err, Error::Lnd { status: 503, code: 14, message } if message.starts_with("the RPC server")),
+            "{:?}",
+            err
+        );
+        assert!(err.is_starting());
+
+        let (port, _) = lnd(
+            "500 Internal Server Error",
+            r#"{"code":2,"message":"unable to get best block info","details":[]}"#,
+        );
+        let err = client(port, "tls.cert").get_info().unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                Error::Lnd {
+                    status: 500,
+                    code: 2,
+                    ..
+                }
+            ),
+            "{:?}",
+            err
+        );
+        assert!(!err.is_starting());
+        assert_eq!(
+            err.to_string(),
+            "lnd error (HTTP 500, code 2): unable to get best block info"
+        );
+
+        let (port, _) = lnd("502 Bad Gateway", "<html>bad gateway</html>");
+        let err = client(port, "tls.cert").get_info().unwrap_err();
+        assert!(
+            matches!(&err, Error::Decode { status: 502, body } if body == "<html>bad gateway</html>"),
+            "{:?}",
+            err
+        );
+
+        let (port, _) = lnd("200 OK", r#"{"state":"SOMETHING_NEW"}"#);
+        let err = client(port, "tls.cert").state().unwrap_err();
+        assert!(
+            matches!(err, Error::Decode { status: 200, .. }),
+            "{:?}",
+            err
+        );
+    }
+}
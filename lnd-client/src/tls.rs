@@ -0,0 +1,51 @@
+//! lnd serves a self-signed certificate marked as a CA, which webpki refuses as a server
+//! certificate even when it is trusted as a root. Instead we pin it: the server must present
+//! exactly the certificate in `tls.cert`, and the handshake signatures are still checked against
+//! its key.
+
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+
+use crate::Error;
+
+struct PinnedCert(Certificate);
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if *end_entity == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "lnd presented a certificate other than the pinned tls.cert".to_owned(),
+            ))
+        }
+    }
+}
+
+/// A TLS configuration that only accepts the first certificate in the PEM `cert`.
+pub fn pinned_config(mut cert: &[u8]) -> Result<ClientConfig, Error> {
+    let der = rustls_pemfile::certs(&mut cert)
+        .map_err(|e| Error::Tls(format!("invalid PEM: {}", e)))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::Tls("no certificate in tls.cert".to_owned()))?;
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    config
+        .dangerous()
+        .set_certificate_verifier(Arc::new(PinnedCert(Certificate(der))));
+    Ok(config)
+}
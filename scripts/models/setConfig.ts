@@ -1,6 +1,6 @@
 import { matches } from "../deps.ts";
 
-const { shape, number, string, boolean } = matches;
+const { shape, number, string, boolean, arrayOf } = matches;
 
 export const matchTor = shape({
   "use-tor-only": boolean,
@@ -34,7 +34,14 @@ export const matchBitcoin = shape({
   "base-fee": number,
   "fee-rate": number,
   "time-lock-delta": number,
-});
+}, [
+  "default-channel-confirmations",
+  "min-htlc",
+  "min-htlc-out",
+  "base-fee",
+  "fee-rate",
+  "time-lock-delta",
+]);
 
 export const matchSweeperOptions = shape({
   "sweeper-maxfeerate": number,
@@ -43,10 +50,14 @@ export const matchSweeperOptions = shape({
   "sweeper-budget-anchorcpfpratio": number,
   "sweeper-budget-deadlinehtlcratio": number,
   "sweeper-budget-nodeadlinehtlcratio": number,
+}, ["sweeper-maxfeerate"]);
+
+export const matchLogging = shape({
+  level: string,
 });
 
 export const matchAdvanced2 = shape({
-  "debug-level": string,
+  logging: matchLogging,
   "db-bolt-no-freelist-sync": boolean,
   "db-bolt-auto-compact": boolean,
   "db-bolt-auto-compact-min-age": number,
@@ -57,22 +68,27 @@ export const matchAdvanced2 = shape({
   "max-channel-fee-allocation": number,
   "max-pending-channels": number,
   "max-commit-fee-rate-anchors": number,
-  "protocol-wumbo-channels": boolean,
-  "protocol-zero-conf": boolean,
-  "protocol-option-scid-alias": boolean,
+  "protocol-wumbo-channels": string,
+  "protocol-zero-conf": string,
+  "protocol-option-scid-alias": string,
   "protocol-no-anchors": boolean,
   "protocol-disable-script-enforced-lease": boolean,
   "protocol-simple-taproot-chans": boolean,
-  "gc-canceled-invoices-on-startup": boolean,
+  "gc-canceled-invoices-on-startup": string,
   bitcoin: matchBitcoin,
   "sweeper": matchSweeperOptions,
-}, ["recovery-window"]);
+}, [
+  "recovery-window",
+  "default-remote-max-htlcs",
+  "max-pending-channels",
+]);
 
 export const matchRoot = shape({
+  preset: string,
   alias: string,
   color: string,
-  "accept-keysend": boolean,
-  "accept-amp": boolean,
+  "accept-keysend": string,
+  "accept-amp": string,
   "reject-htlc": boolean,
   "min-chan-size": number,
   "max-chan-size": number,
@@ -83,10 +99,9 @@ export const matchRoot = shape({
   "control-tor-address": string,
   "peer-tor-address": string,
   "watchtower-tor-address": string,
-   externalip: string,
+  externalip: arrayOf(string),
 }, [
   "alias",
-  "externalip",
   "min-chan-size",
   "max-chan-size",
   "watchtower-tor-address",
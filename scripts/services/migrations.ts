@@ -332,6 +332,75 @@ export const migration: T.ExpectedExports.migration =
           throw new Error("Cannot downgrade");
         },
       },
+      "0.20.1.2": {
+        up: compat.migrations.updateConfig(
+          (config: any) => {
+            // same conversions as the configurator's upgrade steps, so the form can show them
+            if (!Array.isArray(config.externalip)) {
+              config.externalip = config.externalip ? [config.externalip] : [];
+            }
+            const debugLevel = config.advanced?.["debug-level"];
+            if (typeof debugLevel === "string") {
+              const logging: any = { level: "info", subsystems: [] };
+              for (const part of debugLevel.split(",").map((p) => p.trim())) {
+                if (!part) continue;
+                const [subsystem, level] = part.split("=").map((p) => p.trim());
+                if (level === undefined) {
+                  logging.level = subsystem;
+                } else {
+                  logging.subsystems.push({ subsystem, level });
+                }
+              }
+              config.advanced.logging = logging;
+              delete config.advanced["debug-level"];
+            }
+            // Preset keys hold what the user chose, see v5_to_v6 in configurator/src/upgrade.rs.
+            // Values equal to the old form's default are taken as unset.
+            const presetDefaults: Array<[string[], boolean | number]> = [
+              [["accept-keysend"], true],
+              [["accept-amp"], false],
+              [["advanced", "protocol-wumbo-channels"], false],
+              [["advanced", "protocol-option-scid-alias"], false],
+              [["advanced", "protocol-zero-conf"], false],
+              [["advanced", "allow-circular-route"], false],
+              [["advanced", "max-pending-channels"], 5],
+              [["advanced", "default-remote-max-htlcs"], 483],
+              [["advanced", "gc-canceled-invoices-on-startup"], false],
+              [["advanced", "bitcoin", "default-channel-confirmations"], 3],
+              [["advanced", "bitcoin", "min-htlc"], 1],
+              [["advanced", "bitcoin", "min-htlc-out"], 1000],
+              [["advanced", "bitcoin", "base-fee"], 1000],
+              [["advanced", "bitcoin", "fee-rate"], 1],
+              [["advanced", "bitcoin", "time-lock-delta"], 40],
+              [["advanced", "sweeper", "sweeper-maxfeerate"], 1000],
+            ];
+            for (const [path, defaultValue] of presetDefaults) {
+              const key = path[path.length - 1];
+              const parent = path.slice(0, -1).reduce(
+                (value: any, key) => value?.[key],
+                config,
+              );
+              if (!parent || !(key in parent)) continue;
+              const value = parent[key];
+              if (typeof value === "boolean") {
+                parent[key] = value === defaultValue
+                  ? "default"
+                  : value
+                  ? "enabled"
+                  : "disabled";
+              } else if (value === defaultValue) {
+                parent[key] = null;
+              }
+            }
+            return config;
+          },
+          true,
+          { version: "0.20.1.2", type: "up" }
+        ),
+        down: () => {
+          throw new Error("Cannot downgrade");
+        },
+      },
     },
-    "0.20.1"
+    "0.20.1.2"
   );
@@ -1,5 +1,5 @@
 import { compat, matches, types as T, util, YAML } from "../deps.ts";
-const { shape, string, number, boolean } = matches;
+const { shape, string, number, boolean, arrayOf } = matches;
 
 const nodeInfoMatcher = shape({
   identity_pubkey: string,
@@ -7,7 +7,8 @@ const nodeInfoMatcher = shape({
   block_height: number,
   synced_to_chain: boolean,
   synced_to_graph: boolean,
-});
+  uris: arrayOf(string),
+}, ["uris"]);
 
 const noPropertiesFound = {
   result: {
@@ -84,6 +85,11 @@ export const properties: T.ExpectedExports.properties = async (
     const nodeInfoJson = await nodeInfo.json();
     if (!nodeInfoMatcher.test(nodeInfoJson)) return wrongShape(nodeInfoJson);
 
+    // lnd lists a URI for every address it advertises; the onion one is shown above
+    const clearnetUris = (nodeInfoJson.uris ?? []).filter(
+      (uri) => !uri.includes(".onion:")
+    );
+
     const stats: T.Properties = {
       version: 2,
       data: {
@@ -113,6 +119,20 @@ export const properties: T.ExpectedExports.properties = async (
           qr: true,
           masked: true,
         },
+        ...Object.fromEntries(
+          clearnetUris.map((uri) => [
+            `Node URI (${uri.split("@")[1]})`,
+            {
+              type: "string" as const,
+              value: uri,
+              description:
+                "Give this to others to allow them to add your LND node as a peer over clearnet",
+              copyable: true,
+              qr: true,
+              masked: true,
+            },
+          ])
+        ),
         "LND Connect gRPC URL": {
           type: "string",
           value: `lndconnect://${controlTorAddress}:10009?cert=${cert}&macaroon=${macaroonBase64URL}`,
@@ -1,6 +1,27 @@
 import { compat, types as T } from "../deps.ts";
 import { matchRoot, Root } from "../models/setConfig.ts";
 
+// Layout version of config.yaml, see CURRENT_VERSION in configurator/src/upgrade.rs. The form
+// always saves the current layout. A configurator test fails when the two differ.
+const CONFIG_VERSION = 6;
+
+// Protocol features presets enable, see Preset::values in configurator/src/presets.rs.
+const PRESET_FEATURES: { [preset: string]: string[] } = {
+  "routing-node": ["protocol-wumbo-channels"],
+  "mobile-backend": ["protocol-option-scid-alias", "protocol-zero-conf"],
+};
+
+// Whether an `advanced` protocol feature ends up enabled: a value the user chose, or else the
+// preset's. None of them is enabled by default.
+function featureEnabled(
+  config: Root,
+  feature: "protocol-wumbo-channels" | "protocol-zero-conf",
+): boolean {
+  const value = config.advanced[feature];
+  if (value !== "default") return value === "enabled";
+  return (PRESET_FEATURES[config.preset] ?? []).includes(feature);
+}
+
 type Check = {
   currentError(config: Root): string | void;
 };
@@ -25,7 +46,7 @@ const configRules: Array<Check> = [
   {
     currentError(config) {
       if (config["max-chan-size"]) {
-        if (!config.advanced["protocol-wumbo-channels"] && config["max-chan-size"] > 16777215) {
+        if (!featureEnabled(config, "protocol-wumbo-channels") && config["max-chan-size"] > 16777215) {
           return "'Advanced > Enable Wumbo Channels' must be enabled to set a max channel size larger than 0.16777215 BTC'";
         }
       }
@@ -33,14 +54,17 @@ const configRules: Array<Check> = [
   },
   {
     currentError(config) {
-      if (config.advanced["protocol-zero-conf"] && !config.advanced["protocol-option-scid-alias"]) {
-        return "'Advanced > Enable option-scid-alias Channels' must be enabled to enable zero-conf channels'";
+      if (
+        featureEnabled(config, "protocol-zero-conf") &&
+        config.advanced["protocol-option-scid-alias"] === "disabled"
+      ) {
+        return "'Advanced > Enable option-scid-alias Channels' must not be disabled to enable zero-conf channels'";
       }
     },
   },
   {
     currentError(config) {
-      if (config.advanced["protocol-zero-conf"] && config.advanced["protocol-no-anchors"]) {
+      if (featureEnabled(config, "protocol-zero-conf") && config.advanced["protocol-no-anchors"]) {
         return "'Advanced > Disable Anchor Channels' must be disabled to enable zero-conf channels'";
       }
     },
@@ -67,5 +91,9 @@ export const setConfig: T.ExpectedExports.setConfig = async (
     config.bitcoind.type === "internal"
       ? { "bitcoind": [] }
       : {};
-  return await compat.setConfig(effects, input, dependsOn);
+  return await compat.setConfig(
+    effects,
+    { ...input, version: CONFIG_VERSION },
+    dependsOn,
+  );
 };